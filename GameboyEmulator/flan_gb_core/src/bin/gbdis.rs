@@ -0,0 +1,114 @@
+//! Disassembles a Game Boy ROM into RGBDS syntax.
+//!
+//! Usage: `gbdis <rom> [bank | [bank:]start-end]`, with all numbers in hex. Without a range the
+//! whole ROM is disassembled, one bank after another.
+
+use std::{env, fs, process::ExitCode};
+
+use flan_gb_core::disassemble;
+
+const BANK_SIZE: usize = 0x4000;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(rom_path) = args.first() else {
+        eprintln!("Usage: gbdis <rom> [bank | [bank:]start-end]");
+        return ExitCode::from(2);
+    };
+
+    let rom = match fs::read(rom_path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Unable to read ROM file \"{rom_path}\": {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let bank_count = rom.len().div_ceil(BANK_SIZE);
+
+    // Work out which banks and address ranges to disassemble
+    let ranges = match args.get(1) {
+        None => (0..bank_count).map(bank_range).collect(),
+        Some(arg) => match parse_range(arg) {
+            Some(range) => vec![range],
+            None => {
+                eprintln!("Invalid range \"{arg}\", expected bank or [bank:]start-end in hex");
+                return ExitCode::from(2);
+            }
+        },
+    };
+
+    for (bank, start, end) in ranges {
+        if bank >= bank_count {
+            eprintln!("Bank ${bank:02X} is past the end of the ROM");
+            return ExitCode::FAILURE;
+        }
+        disassemble_range(&rom, bank, start, end);
+    }
+
+    ExitCode::SUCCESS
+}
+
+// The whole address range a bank is mapped to
+fn bank_range(bank: usize) -> (usize, u16, u16) {
+    match bank {
+        0 => (0, 0x0000, 0x3FFF),
+        _ => (bank, 0x4000, 0x7FFF),
+    }
+}
+
+fn parse_range(text: &str) -> Option<(usize, u16, u16)> {
+    let hex = |text: &str| u16::from_str_radix(text.trim_start_matches('$'), 16).ok();
+
+    let Some((start, end)) = text.split_once('-') else {
+        let bank = usize::from_str_radix(text.trim_start_matches('$'), 16).ok()?;
+        return Some(bank_range(bank));
+    };
+    let (bank, start) = match start.split_once(':') {
+        Some((bank, start)) => (Some(usize::from_str_radix(bank, 16).ok()?), hex(start)?),
+        None => (None, hex(start)?),
+    };
+    let end = hex(end)?;
+
+    // Without a bank, take the first bank that can be mapped there. The range has to stay inside that bank.
+    let bank = bank.unwrap_or(if start < 0x4000 { 0 } else { 1 });
+    let (_, bank_start, bank_end) = bank_range(bank);
+    if start > end || start < bank_start || end > bank_end {
+        return None;
+    }
+    Some((bank, start, end))
+}
+
+fn disassemble_range(rom: &[u8], bank: usize, start: u16, end: u16) {
+    // A section header makes the output assemble back into the same bytes
+    if bank == 0 {
+        println!("SECTION \"ROM0 ${start:04X}\", ROM0[${start:04X}]");
+    } else {
+        println!("SECTION \"ROMX ${bank:02X}:{start:04X}\", ROMX[${start:04X}], BANK[${bank:02X}]");
+    }
+
+    let offset_of = |address: u16| bank * BANK_SIZE + (address as usize & (BANK_SIZE - 1));
+    let mut address = start as u32;
+    while address <= end as u32 {
+        // The last bank can be cut short
+        let offset = offset_of(address as u16);
+        if offset >= rom.len() {
+            break;
+        }
+        let bank_end = bank * BANK_SIZE + BANK_SIZE;
+        let bytes = &rom[offset..(offset + 3).min(bank_end).min(rom.len())];
+        let instruction = disassemble(bytes, address as u16);
+
+        let raw: Vec<String> = bytes
+            .iter()
+            .take(instruction.length as usize)
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        println!(
+            "    {:<24} ; {bank:02X}:{address:04X}  {}",
+            instruction.text,
+            raw.join(" ")
+        );
+
+        address += instruction.length as u32;
+    }
+}
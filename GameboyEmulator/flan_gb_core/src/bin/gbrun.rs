@@ -0,0 +1,461 @@
+//! Runs a ROM without a window or sound card, for batch jobs and CI.
+//!
+//! Usage: `gbrun <rom> [options]`, run with `--help` for the list. Addresses and values are in hex.
+//!
+//! Exit codes: 0 when the stop condition was met (or the frames ran out and there was no
+//! condition), 1 when the frames ran out first, the screen didn't match the reference or a movie
+//! desynced, 2 for bad arguments, 3 when the ROM can't be loaded.
+
+use std::{fs, path::Path, process::ExitCode};
+
+use flan_gb_core::{AudioMode, GameBoy, InputState, MemoryInit, Movie, WatchKind};
+
+const USAGE: &str = "\
+Usage: gbrun <rom> [options]
+
+Running:
+  --frames N                 stop after N frames (default 3600, or the length of the movie)
+  --skip-boot                start at $0100 instead of running the boot ROM
+  --seed N                   power on with memory filled from this seed instead of a random one
+  --memory-init MODE         what RAM holds at power-on: zeros, ff, random (default), dmg for
+                             the pattern a DMG tends to power on with, or poison to log every
+                             read of RAM that was never written
+  --input FILE               scripted joypad input, see below
+  --record-movie FILE        record the input of the run as a movie
+  --play-movie FILE          play a movie back, checking every frame against the recording
+  --record-audio FILE        record the audio of the whole run as a 16-bit WAV
+  --record-channels          also record each channel on its own, to FILE.ch1.wav and up
+  --sample-rate N            sample rate of the recording (default 48000)
+  --fast-audio               use the cheaper 32768 Hz audio path
+
+Stop conditions, the first one met ends the run:
+  --until-pc [bank:]ADDR     PC reaches an address
+  --until-mem ADDR=VALUE     a byte in memory is written with this value
+  --until-serial TEXT        the serial output contains this text
+
+Output, written when the run ends:
+  --dump-screen FILE         the framebuffer as a PNG
+  --dump-memory ADDR:LEN:FILE  a range of memory as raw bytes
+  --print-serial             print everything sent over serial
+
+Checking the screen when the run ends:
+  --compare-screen FILE      compare the framebuffer against a reference PNG
+  --tolerance N              how far off a colour channel can be and still match (default 0)
+  --diff-screen FILE         write an image with the differing pixels in red
+
+The input file has one line per change, `FRAME BUTTONS`, where the buttons are held from that
+frame on. Buttons are comma separated: up, down, left, right, a, b, start, select, or - for none.
+Lines starting with # are ignored.";
+
+struct Options {
+    rom_path: String,
+    frames: Option<u32>,
+    skip_boot: bool,
+    seed: Option<u64>,
+    memory_init: Option<MemoryInit>,
+    input: Vec<(u32, InputState)>,
+    record_movie: Option<String>,
+    play_movie: Option<String>,
+    record_audio: Option<String>,
+    record_channels: bool,
+    sample_rate: Option<u32>,
+    fast_audio: bool,
+    until_pc: Option<(Option<usize>, u16)>,
+    until_memory: Option<(u16, u8)>,
+    until_serial: Option<String>,
+    dump_screen: Option<String>,
+    dump_memory: Vec<(u16, u16, String)>,
+    print_serial: bool,
+    compare_screen: Option<String>,
+    tolerance: u8,
+    diff_screen: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let rom = match fs::read(&options.rom_path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Unable to read ROM file \"{}\": {e}", options.rom_path);
+            return ExitCode::from(3);
+        }
+    };
+
+    // A movie powers on the way it was recorded, anything else powers on as asked
+    let playback = match &options.play_movie {
+        Some(path) => match fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| Movie::from_bytes(&bytes).map_err(|e| e.to_string()))
+        {
+            Ok(movie) => Some(movie),
+            Err(e) => {
+                eprintln!("Unable to load movie \"{path}\": {e}");
+                return ExitCode::from(2);
+            }
+        },
+        None => None,
+    };
+    let mut game_boy = match &playback {
+        Some(movie) => match movie.power_on(rom) {
+            Ok(game_boy) => game_boy,
+            Err(e) => {
+                eprintln!("Unable to play movie: {e}");
+                return ExitCode::from(2);
+            }
+        },
+        None => {
+            let mut game_boy = match options.seed {
+                Some(seed) => GameBoy::with_power_on_seed(seed),
+                None => GameBoy::new(),
+            };
+            if let Some(memory_init) = options.memory_init {
+                game_boy.set_memory_init(memory_init);
+            }
+            if !game_boy.load_rom(rom) {
+                eprintln!(
+                    "Unable to load ROM file \"{}\": it's too small to be a Game Boy ROM",
+                    options.rom_path
+                );
+                return ExitCode::from(3);
+            }
+            if options.skip_boot {
+                game_boy.skip_boot_rom();
+            }
+            game_boy
+        }
+    };
+    game_boy.set_serial_capture(true);
+    if let Some(movie) = &playback {
+        println!(
+            "Playing movie by \"{}\", {} frames and {} rerecords: {}",
+            movie.author,
+            movie.len(),
+            movie.rerecords(),
+            movie.description
+        );
+    }
+    let mut recording = options.record_movie.as_ref().map(|_| Movie::new(&game_boy));
+    let frames = options.frames.unwrap_or(match &playback {
+        Some(movie) => movie.len() as u32,
+        None => 3600,
+    });
+    if options.fast_audio {
+        game_boy.set_audio_mode(AudioMode::Fast);
+    }
+    if let Some(sample_rate) = options.sample_rate {
+        game_boy.set_audio_sample_rate(sample_rate);
+    }
+    if let Some(path) = &options.record_audio {
+        if let Err(e) = game_boy.start_audio_recording(path, options.record_channels) {
+            eprintln!("Unable to create audio recording \"{path}\": {e}");
+            return ExitCode::from(2);
+        }
+    }
+
+    // PC and memory conditions are checked by the debugger, so they're caught on the exact instruction
+    if let Some((bank, address)) = options.until_pc {
+        game_boy.set_debugger_enabled(true);
+        game_boy.add_breakpoint(address, bank);
+    }
+    if let Some((address, _)) = options.until_memory {
+        game_boy.set_debugger_enabled(true);
+        game_boy.add_watchpoint(address, address, WatchKind::Write);
+    }
+    let has_condition = options.until_pc.is_some()
+        || options.until_memory.is_some()
+        || options.until_serial.is_some();
+
+    // Run until something tells us to stop
+    let mut serial = Vec::new();
+    let mut input = options.input.iter().peekable();
+    let mut input_state = InputState::default();
+    let mut desync = None;
+    let mut met = None;
+    let mut frame = 0;
+    while frame < frames && met.is_none() {
+        while let Some((_, state)) = input.next_if(|(start, _)| *start <= frame) {
+            input_state = *state;
+        }
+        if let Some(state) = playback
+            .as_ref()
+            .and_then(|movie| movie.input(frame as usize))
+        {
+            input_state = state;
+        }
+        game_boy.update_input(&input_state);
+
+        game_boy.run_frame();
+        let frame_finished = game_boy.debugger_paused().is_none();
+        serial.extend(game_boy.take_serial_output());
+
+        if game_boy.debugger_paused().is_some() {
+            let registers = game_boy.registers();
+            if options.until_pc.is_some_and(|(bank, address)| {
+                registers.pc == address && (bank.is_none() || bank == game_boy.rom_bank_at(address))
+            }) {
+                met = Some(format!("PC reached ${:04X}", registers.pc));
+            } else if let Some((address, value)) = options.until_memory {
+                if game_boy.read_memory(address) == value {
+                    met = Some(format!("${address:04X} was set to ${value:02X}"));
+                }
+            }
+
+            // Not what we're looking for, or a frame boundary is still to come, carry on either way
+            game_boy.debug_continue();
+            if met.is_none() {
+                continue;
+            }
+        }
+        if let Some(text) = &options.until_serial {
+            if String::from_utf8_lossy(&serial).contains(text.as_str()) {
+                met = Some(format!("serial output contained \"{text}\""));
+            }
+        }
+
+        // Movies only deal in whole frames, not ones a stop condition cut short
+        if frame_finished {
+            if let Some(movie) = &mut recording {
+                movie.record_frame(&input_state, &game_boy);
+            }
+            if let (Some(movie), None) = (&playback, &desync) {
+                desync = movie.verify_frame(frame as usize, &game_boy).err();
+            }
+        }
+
+        frame += 1;
+    }
+
+    if let Err(e) = game_boy.stop_audio_recording() {
+        eprintln!("Unable to finish audio recording: {e}");
+    }
+
+    // Report and dump everything
+    match &met {
+        Some(reason) => println!("Stopped after {frame} frames: {reason}"),
+        None => println!("Stopped after {frame} frames"),
+    }
+    game_boy.print_reg_state();
+    if options.print_serial {
+        println!("Serial output: {}", String::from_utf8_lossy(&serial));
+    }
+    if let Some(path) = &options.dump_screen {
+        if let Err(e) = game_boy.save_screenshot(path, 1) {
+            eprintln!("Unable to write screenshot \"{path}\": {e}");
+        }
+    }
+    for (start, length, path) in &options.dump_memory {
+        game_boy.dump_memory(path, *start, *length);
+    }
+    if let (Some(movie), Some(path)) = (&recording, &options.record_movie) {
+        match fs::write(path, movie.to_bytes()) {
+            Ok(()) => println!("Recorded {} frames to \"{path}\"", movie.len()),
+            Err(e) => eprintln!("Unable to write movie \"{path}\": {e}"),
+        }
+    }
+    match &desync {
+        Some(e) => println!("Movie doesn't match: {e}"),
+        None if playback.is_some() => println!("Movie played back without desyncing"),
+        None => (),
+    }
+
+    // Check the screen against the reference
+    let mut screen_matches = true;
+    if let Some(path) = &options.compare_screen {
+        let diff_path = options.diff_screen.as_deref().map(Path::new);
+        match game_boy.compare_screenshot(path, options.tolerance, diff_path) {
+            Ok(comparison) if comparison.matches() => println!("Screen matches \"{path}\""),
+            Ok(comparison) => {
+                println!(
+                    "Screen doesn't match \"{path}\": {} pixels differ, by up to {}",
+                    comparison.differing_pixels, comparison.max_difference
+                );
+                screen_matches = false;
+            }
+            Err(e) => {
+                eprintln!("Unable to compare screen against \"{path}\": {e}");
+                screen_matches = false;
+            }
+        }
+    }
+
+    match (met.is_some() || !has_condition) && screen_matches && desync.is_none() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        rom_path: String::new(),
+        frames: None,
+        skip_boot: false,
+        seed: None,
+        memory_init: None,
+        input: Vec::new(),
+        record_movie: None,
+        play_movie: None,
+        record_audio: None,
+        record_channels: false,
+        sample_rate: None,
+        fast_audio: false,
+        until_pc: None,
+        until_memory: None,
+        until_serial: None,
+        dump_screen: None,
+        dump_memory: Vec::new(),
+        print_serial: false,
+        compare_screen: None,
+        tolerance: 0,
+        diff_screen: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--help" | "-h" => return Err(String::new()),
+            "--frames" => {
+                let text = value()?;
+                let frames = text
+                    .parse()
+                    .map_err(|_| format!("Invalid frame count \"{text}\""))?;
+                options.frames = Some(frames);
+            }
+            "--skip-boot" => options.skip_boot = true,
+            "--seed" => {
+                let text = value()?;
+                let seed = text
+                    .parse()
+                    .map_err(|_| format!("Invalid seed \"{text}\""))?;
+                options.seed = Some(seed);
+            }
+            "--memory-init" => options.memory_init = Some(value()?.parse()?),
+            "--input" => options.input = parse_input_file(&value()?)?,
+            "--record-movie" => options.record_movie = Some(value()?),
+            "--play-movie" => options.play_movie = Some(value()?),
+            "--until-pc" => {
+                let text = value()?;
+                let parsed = match text.split_once(':') {
+                    Some((bank, address)) => usize::from_str_radix(bank, 16)
+                        .ok()
+                        .zip(parse_hex(address))
+                        .map(|(bank, address)| (Some(bank), address)),
+                    None => parse_hex(&text).map(|address| (None, address)),
+                };
+                options.until_pc = Some(parsed.ok_or(format!("Invalid address \"{text}\""))?);
+            }
+            "--until-mem" => {
+                let text = value()?;
+                let parsed = text
+                    .split_once('=')
+                    .and_then(|(address, value)| parse_hex(address).zip(parse_hex(value)))
+                    .filter(|(_, value)| *value <= 0xFF);
+                let (address, value) =
+                    parsed.ok_or(format!("Invalid condition \"{text}\", expected ADDR=VALUE"))?;
+                options.until_memory = Some((address, value as u8));
+            }
+            "--until-serial" => options.until_serial = Some(value()?),
+            "--record-audio" => options.record_audio = Some(value()?),
+            "--record-channels" => options.record_channels = true,
+            "--sample-rate" => {
+                let text = value()?;
+                let sample_rate = text
+                    .parse()
+                    .map_err(|_| format!("Invalid sample rate \"{text}\""))?;
+                options.sample_rate = Some(sample_rate);
+            }
+            "--fast-audio" => options.fast_audio = true,
+            "--dump-screen" => options.dump_screen = Some(value()?),
+            "--dump-memory" => {
+                let text = value()?;
+                let mut parts = text.splitn(3, ':');
+                let parsed = match (parts.next(), parts.next(), parts.next()) {
+                    (Some(start), Some(length), Some(path)) => parse_hex(start)
+                        .zip(parse_hex(length))
+                        .map(|(start, length)| (start, length, path.to_string())),
+                    _ => None,
+                };
+                let dump =
+                    parsed.filter(|(start, length, _)| *start as u32 + *length as u32 <= 0x10000);
+                options.dump_memory.push(dump.ok_or(format!(
+                    "Invalid memory dump \"{text}\", expected ADDR:LEN:FILE"
+                ))?);
+            }
+            "--print-serial" => options.print_serial = true,
+            "--compare-screen" => options.compare_screen = Some(value()?),
+            "--tolerance" => {
+                let text = value()?;
+                options.tolerance = text
+                    .parse()
+                    .map_err(|_| format!("Invalid tolerance \"{text}\""))?;
+            }
+            "--diff-screen" => options.diff_screen = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ => options.rom_path = arg,
+        }
+    }
+
+    if options.rom_path.is_empty() {
+        return Err("No ROM given".to_string());
+    }
+    // A movie brings its own input and power-on state
+    if options.play_movie.is_some()
+        && (!options.input.is_empty()
+            || options.seed.is_some()
+            || options.memory_init.is_some()
+            || options.skip_boot)
+    {
+        return Err(
+            "--play-movie can't be combined with --input, --seed, --memory-init or --skip-boot"
+                .to_string(),
+        );
+    }
+    Ok(options)
+}
+
+fn parse_input_file(path: &str) -> Result<Vec<(u32, InputState)>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read input file \"{path}\": {e}"))?;
+
+    let mut input = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = || format!("{path}:{}: invalid line \"{line}\"", line_number + 1);
+        let (frame, buttons) = line.split_once(char::is_whitespace).unwrap_or((line, "-"));
+        let frame = frame.parse().map_err(|_| error())?;
+        let mut state = InputState::default();
+        for button in buttons.split(',').map(str::trim) {
+            match button {
+                "up" => state.up = true,
+                "down" => state.down = true,
+                "left" => state.left = true,
+                "right" => state.right = true,
+                "a" => state.a = true,
+                "b" => state.b = true,
+                "start" => state.start = true,
+                "select" => state.select = true,
+                "-" | "" => (),
+                _ => return Err(error()),
+            }
+        }
+        input.push((frame, state));
+    }
+
+    // Changes are applied in order of frame, whatever order the file lists them in
+    input.sort_by_key(|(frame, _)| *frame);
+    Ok(input)
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.trim_start_matches('$').trim_start_matches("0x"), 16).ok()
+}
@@ -0,0 +1,435 @@
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+
+use apu::gb_audio_recorder::AudioRecorder;
+use apu::gb_audio_synth::AudioSynth;
+pub use apu::gb_audio_synth::{AudioMode, FAST_AUDIO_SAMPLE_RATE};
+use debug::gb_debugger::Debugger;
+pub use debug::gb_debugger::{BreakReason, WatchKind};
+pub use debug::gb_disassembler::{disassemble, Instruction};
+use mapper::gb_mapper::Mapper;
+pub use misc::gb_link_cable::LinkCable;
+pub use misc::gb_png::ImageError;
+use misc::gb_poison::WrittenMemory;
+pub use misc::gb_power_on::MemoryInit;
+pub use misc::gb_printer::Printer;
+pub use misc::gb_screenshot::ImageComparison;
+pub use misc::gb_serial::SerialDevice;
+pub use state::gb_movie::{Movie, MovieError};
+pub use state::gb_rewind::RewindBuffer;
+pub use state::gb_state_io::SaveStateError;
+
+mod apu;
+mod cpu;
+mod debug;
+mod mapper;
+mod misc;
+mod ppu;
+mod state;
+
+pub enum FlagMask {
+    Zero = 0x80,
+    Neg = 0x40,
+    Half = 0x20,
+    Carry = 0x10,
+}
+
+#[derive(Clone)]
+pub struct PpuFifoElement {
+    pub color: u8,      //0, 1, 2, 3
+    pub source: u8,     //0: bg, 1: sprite 1, 2: sprite 2
+    pub palette: u8,    //0..=7, CGB palette index
+    pub priority: bool, //CGB only: BG tile attribute priority bit
+    pub oam_index: u8,  //0..=39, which sprite the pixel came from
+}
+
+struct OamEntry {
+    posy: u8,
+    posx: u8,
+    tile: u8,
+    attr: u8,
+    index: u8,
+}
+
+struct AudioSource {}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct InputState {
+    pub down: bool,
+    pub up: bool,
+    pub left: bool,
+    pub right: bool,
+    pub start: bool,
+    pub select: bool,
+    pub b: bool,
+    pub a: bool,
+}
+
+/// A snapshot of the CPU registers
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Registers {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+}
+
+pub struct GameBoy {
+    // Memory Map
+    bios: [u8; 0x100],
+    rom: Vec<u8>,
+    rom_hash: u64,
+    eram: Vec<u8>,
+    mapper: Box<dyn Mapper>,
+    vram: [u8; 0x4000],
+    wram: [u8; 0x8000],
+    oam: [u8; 0xA0],
+    io: [u8; 0x80],
+    hram: [u8; 0x7F],
+    bg_palette_ram: [u8; 0x40],
+    obj_palette_ram: [u8; 0x40],
+
+    // State
+    ie: u8,
+    ime: u8,
+    pc: u16,
+    sp: u16,
+
+    // PPU
+    ppu_lx: u8,
+    ppu_ly: u8,
+    ppu_mode: u8,
+    ppu_dots_into_curr_mode: u16,
+    ppu_dots_into_curr_line: u16,
+    ppu_fifo: VecDeque<PpuFifoElement>,
+    ppu_tilemap_x: u8, //0..=31
+    ppu_tilemap_y: u8, //0..=31
+    ppu_pixels_to_discard: u8,
+    ppu_sprite_buffer: Vec<OamEntry>,
+    framebuffer: Vec<u32>,
+
+    // APU
+    apu_buffer: [[u16; 512]; 2],
+    apu_buffer_to_use: usize,
+    apu_buffer_write_index: usize,
+    apu_buffer_read_index: usize,
+    apu_output: Vec<i16>,
+    apu_sound_output: [u8; 4],
+    apu_pulse1_freq_counter: u16,
+    apu_pulse1_env_counter: u8,
+    apu_pulse1_duty_step: usize,
+    apu_pulse1_length_timer: u8,
+    apu_pulse1_enabled: bool,
+    apu_pulse1_curr_volume: u8,
+
+    apu_pulse2_freq_counter: u16,
+    apu_pulse2_env_counter: u8,
+    apu_pulse2_duty_step: usize,
+    apu_pulse2_length_timer: u8,
+    apu_pulse2_enabled: bool,
+    apu_pulse2_curr_volume: u8,
+
+    apu_wave_freq_counter: u16,
+    apu_wave_env_counter: u8,
+    apu_wave_duty_step: usize,
+    apu_wave_length_timer: u8,
+    apu_wave_enabled: bool,
+
+    apu_noise_freq_counter: u16,
+    apu_noise_env_counter: u8,
+    apu_noise_duty_step: usize,
+    apu_noise_length_timer: u8,
+    apu_noise_enabled: bool,
+    apu_noise_curr_volume: u8,
+
+    apu_pulse1_sweep_timer: u8,
+    apu_pulse1_sweep_shadow_freq: u16,
+    apu_pulse1_sweep_enable: bool,
+    apu_clock_timer: u32,
+    apu_clock: u32,
+    audio_mode: AudioMode,
+    audio_synth: AudioSynth,
+    audio_recorder: Option<AudioRecorder>,
+
+    // Registers
+    reg_a: u8,
+    reg_f: u8,
+    reg_b: u8,
+    reg_c: u8,
+    reg_d: u8,
+    reg_e: u8,
+    reg_h: u8,
+    reg_l: u8,
+
+    // CGB
+    cgb_mode: bool,
+    vram_bank: usize,
+    wram_bank: usize,
+    double_speed: bool,
+    double_speed_skip_cycle: bool,
+    hdma_source: u16,
+    hdma_destination: u16,
+    hdma_blocks_left: u8,
+    hdma_active: bool,
+
+    // Misc emulation
+    pub times: [u8; 256],
+    curr_cycles_to_wait: u32,
+    last_opcode: u8,
+    last_opcode_cycles: u32,
+    instruction_pc: u16,
+    new_instruction_tick: bool,
+    rom_chip_enabled: bool,
+    cpu_cycle_counter: u32,
+    elapsed_dots: u64,
+    is_halted: bool,
+    is_stopped: bool,
+    timer_div: u16,
+    timer_overflow: bool,
+    oam_dma_counter: u8,
+    oam_dma_source: u16,
+    joypad_state: u8,
+    window_is_rendering: bool,
+    save_path: String,
+    power_on_seed: u64,
+    memory_init: MemoryInit,
+    written_memory: Option<WrittenMemory>,
+    serial_device: Option<Box<dyn SerialDevice>>,
+    serial_cycles_left: u32,
+    serial_poll_timer: u32,
+    serial_output: Option<Vec<u8>>,
+    test_bus: Option<Vec<u8>>,
+
+    // Debug
+    debugger: Debugger,
+    trace_log: Option<BufWriter<Box<dyn Write>>>,
+}
+
+impl GameBoy {
+    /// The 160x144 screen as 0xAARRGGBB pixels, row by row
+    pub fn framebuffer(&self) -> &[u32] {
+        &self.framebuffer
+    }
+
+    /// How much time has passed on the Game Boy since it was turned on, going by its own clock.
+    /// Loading a state or resetting doesn't change it.
+    pub fn emulated_time(&self) -> Duration {
+        Duration::from_nanos((self.elapsed_dots as u128 * 1_000_000_000 / 4_194_304) as u64)
+    }
+
+    /// The seed the memory was filled with at power-on
+    pub fn power_on_seed(&self) -> u64 {
+        self.power_on_seed
+    }
+
+    /// What RAM was filled with at power-on
+    pub fn memory_init(&self) -> MemoryInit {
+        self.memory_init
+    }
+
+    /// Hash of the inserted ROM, used to check that save states belong to this game
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.reg_a,
+            f: self.reg_f,
+            b: self.reg_b,
+            c: self.reg_c,
+            d: self.reg_d,
+            e: self.reg_e,
+            h: self.reg_h,
+            l: self.reg_l,
+            sp: self.sp,
+            pc: self.pc,
+            ime: self.ime != 0,
+        }
+    }
+
+    pub fn set_registers(&mut self, registers: &Registers) {
+        self.reg_a = registers.a;
+        self.reg_f = registers.f;
+        self.reg_b = registers.b;
+        self.reg_c = registers.c;
+        self.reg_d = registers.d;
+        self.reg_e = registers.e;
+        self.reg_h = registers.h;
+        self.reg_l = registers.l;
+        self.sp = registers.sp;
+        self.pc = registers.pc;
+        self.ime = registers.ime as u8;
+    }
+
+    /// Reads a byte the way the CPU would see it right now, without any side effects
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.peek_byte_from_memory(address)
+    }
+
+    /// Writes a byte the way the CPU would, without taking any time
+    pub fn write_memory(&mut self, address: u16, value: u8) {
+        self.poke_byte_to_memory(address, value);
+    }
+
+    /// Replaces the whole memory map with 64 KiB of plain RAM, so the CPU can be tested on its own
+    pub fn use_test_bus(&mut self) {
+        self.test_bus = Some(vec![0; 0x10000]);
+    }
+
+    /// Starts or stops keeping the bytes the game sends over the serial port, for `take_serial_output`.
+    /// It's off by default, so the bytes don't pile up when nobody takes them.
+    pub fn set_serial_capture(&mut self, enabled: bool) {
+        match enabled {
+            true => {
+                self.serial_output.get_or_insert_with(Vec::new);
+            }
+            false => self.serial_output = None,
+        }
+    }
+
+    /// Takes all the bytes the game has sent over the serial port since the last call.
+    /// Always empty unless `set_serial_capture` turned capturing on.
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.serial_output
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Takes all the interleaved stereo samples the APU has produced since the last call.
+    /// The samples are signed 16-bit at `audio_sample_rate` Hz.
+    pub fn take_audio_samples(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.apu_output)
+    }
+
+    pub fn render_screen(
+        &self,
+        buffer: &mut [u32],
+        offset_x: usize,
+        offset_y: usize,
+        scale: usize,
+        width: usize,
+    ) {
+        // Render outline
+        let end_x = offset_x + 160 * scale;
+        let end_y = offset_y + 144 * scale;
+        draw_line_border(buffer, offset_x, end_x, offset_y, end_y, width);
+
+        for y in 0..144 * scale {
+            for x in 0..160 * scale {
+                buffer[(offset_x + x + 1) + (offset_y + y + 1) * width] =
+                    self.framebuffer[(x / scale) + (y / scale) * 160];
+            }
+        }
+    }
+
+    pub fn render_palettes(
+        &self,
+        buffer: &mut [u32],
+        offset_x: usize,
+        offset_y: usize,
+        scale: usize,
+        width: usize,
+    ) {
+        // Get palettes from IO
+        // i was really about to type `ldh a, [$ff47]` wow
+        let bgp = [
+            self.io[0x47] & 0x03,
+            self.io[0x47] >> 2 & 0x03,
+            self.io[0x47] >> 4 & 0x03,
+            self.io[0x47] >> 6 & 0x03,
+        ];
+        let obp0 = [
+            self.io[0x48] & 0x03,
+            self.io[0x48] >> 2 & 0x03,
+            self.io[0x48] >> 4 & 0x03,
+            self.io[0x48] >> 6 & 0x03,
+        ];
+        let obp1 = [
+            self.io[0x49] & 0x03,
+            self.io[0x49] >> 2 & 0x03,
+            self.io[0x49] >> 4 & 0x03,
+            self.io[0x49] >> 6 & 0x03,
+        ];
+        let palettes = [bgp, obp0, obp1];
+
+        // Draw the palettes
+        for (y, palette) in palettes.iter().enumerate() {
+            for (x, color) in palette.iter().enumerate() {
+                draw_rectangle(
+                    buffer,
+                    (((color ^ 0b11) as u32) * (235 / 3)) * 0x00010101,
+                    offset_x + (scale) * x,
+                    offset_x + (scale) * (x + 1),
+                    offset_y + (scale) * y,
+                    offset_y + (scale) * (y + 1),
+                    width,
+                );
+                draw_line_border(
+                    buffer,
+                    offset_x + (scale) * x,
+                    offset_x + (scale) * (x + 1),
+                    offset_y + (scale) * y,
+                    offset_y + (scale) * (y + 1),
+                    width,
+                );
+            }
+        }
+    }
+
+    pub fn save_game_if_possible(&self) {
+        if self.save_path.is_empty() {
+            return;
+        }
+        if !self.eram.is_empty() || self.mapper.has_rtc() {
+            let mut save = self.eram.clone();
+            save.extend(self.mapper.save_footer());
+            std::fs::write(self.save_path.as_str(), save).expect("Couldn't write save file");
+        }
+    }
+}
+
+fn draw_rectangle(
+    buffer: &mut [u32],
+    color: u32,
+    x_1: usize,
+    x_2: usize,
+    y_1: usize,
+    y_2: usize,
+    width: usize,
+) {
+    for y in y_1..=y_2 {
+        for x in x_1..=x_2 {
+            buffer[x + y * width] = color;
+        }
+    }
+}
+
+fn draw_line_border(
+    buffer: &mut [u32],
+    x_1: usize,
+    x_2: usize,
+    y_1: usize,
+    y_2: usize,
+    buffer_width: usize,
+) {
+    for x in (x_1.wrapping_sub(1))..=(x_2 + 1) {
+        buffer[x + (y_1 - 1) * buffer_width] = 0xFFFF00FF;
+        buffer[x + (y_2 + 1) * buffer_width] = 0xFFFF00FF;
+    }
+    for y in (y_1.wrapping_sub(1))..=(y_2 + 1) {
+        buffer[(x_1 - 1) + y * buffer_width] = 0xFFFF00FF;
+        buffer[(x_2 + 1) + y * buffer_width] = 0xFFFF00FF;
+    }
+}
@@ -1,5 +1,4 @@
-use rodio::buffer::SamplesBuffer;
-
+use super::gb_audio_synth::AudioMode;
 use crate::gameboy::GameBoy;
 
 const DUTY_CYCLES: [[u8; 8]; 4] = [
@@ -11,14 +10,22 @@ const DUTY_CYCLES: [[u8; 8]; 4] = [
 
 const VOL_DIV: u16 = 128;
 
+// The fast path starts every sample at this value and adds the channels on top
+const MIX_SILENCE: u16 = 32767;
+
 impl GameBoy {
-    // One cycle is one tick in the 1048576 Hz clock
+    // One cycle is one tick in the 2097152 Hz clock
     pub(in super::super) fn run_apu_cycle(&mut self) {
         // If sound is disabled, reset all registers and dont output any audio
         if self.io[0x26] & (1 << 7) == 0 {
             for addr in 0x10..=0x25 {
                 self.io[addr] = 0x00;
             }
+
+            // The accurate path keeps going with silence, so the output doesn't stall
+            if self.audio_mode == AudioMode::Accurate {
+                self.synthesize_audio();
+            }
             return;
         }
 
@@ -39,9 +46,12 @@ impl GameBoy {
             self.handle512_channel_4();
         }
 
-        if self.apu_buffer_write_index % (1 << 6) == 0 {
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2] = 32767;
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2 + 1] = 32767;
+        let fast_audio = self.audio_mode == AudioMode::Fast;
+        if fast_audio && self.apu_buffer_write_index.is_multiple_of(1 << 6) {
+            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2] =
+                MIX_SILENCE;
+            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2 + 1] =
+                MIX_SILENCE;
         }
 
         self.handle_apu_channel_1();
@@ -49,14 +59,21 @@ impl GameBoy {
         self.handle_apu_channel_3();
         self.handle_apu_channel_4();
 
+        if !fast_audio {
+            self.synthesize_audio();
+            return;
+        }
+
         self.apu_buffer_write_index += 1;
         if self.apu_buffer_write_index == 256 << 6 {
             self.apu_buffer_write_index = 0;
 
-            let apu_source: SamplesBuffer<u16> =
-                SamplesBuffer::new(2, 32768, self.apu_buffer[self.apu_buffer_to_use]);
-                
-            self.apu_sink.append(apu_source);
+            let mixed: Vec<i16> = self.apu_buffer[self.apu_buffer_to_use]
+                .iter()
+                .map(|&sample| (sample as i32 - MIX_SILENCE as i32) as i16)
+                .collect();
+            self.apu_output.extend_from_slice(&mixed);
+            self.record_audio_block(&mixed, None);
             self.apu_buffer_to_use ^= 1;
         }
     }
@@ -83,17 +100,7 @@ impl GameBoy {
         } else {
             self.apu_sound_output[0] = 0;
         }
-        // Add channel 1 to apu buffer left
-        if self.io[0x25] & 0b0001_0000 > 0 {
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2] +=
-                ((self.apu_sound_output[0] as u16) * (16 * ((self.io[0x24] >> 4) & 0x07) as u16))
-                    / VOL_DIV;
-        }
-        // Add channel 1 to apu buffer right
-        if self.io[0x25] & 0b0000_0001 > 0 {
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2 + 1] +=
-                ((self.apu_sound_output[0] as u16) * (16 * ((self.io[0x24]) & 0x07) as u16)) / VOL_DIV;
-        }
+        self.mix_channel(0);
     }
 
     fn handle_apu_channel_2(&mut self) {
@@ -118,17 +125,7 @@ impl GameBoy {
         } else {
             self.apu_sound_output[1] = 0;
         }
-        // Add channel 2 to apu buffer left
-        if self.io[0x25] & 0b0010_0000 > 0 {
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2] +=
-                ((self.apu_sound_output[1] as u16) * (16 * ((self.io[0x24] >> 4) & 0x07) as u16))
-                    / VOL_DIV;
-        }
-        // Add channel 2 to apu buffer right
-        if self.io[0x25] & 0b0000_0010 > 0 {
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2 + 1] +=
-                ((self.apu_sound_output[1] as u16) * (16 * ((self.io[0x24]) & 0x07) as u16)) / VOL_DIV;
-        }
+        self.mix_channel(1);
     }
 
     fn handle_apu_channel_3(&mut self) {
@@ -154,7 +151,7 @@ impl GameBoy {
         // Update channel 3 sound output state
         if self.apu_wave_enabled {
             let mut curr_sample_byte = self.io[0x30 + self.apu_wave_duty_step / 2];
-            if self.apu_wave_duty_step % 2 == 0 {
+            if self.apu_wave_duty_step.is_multiple_of(2) {
                 curr_sample_byte >>= 4;
             }
             self.apu_sound_output[2] = (0x0F - (curr_sample_byte & 0x0F))
@@ -162,17 +159,7 @@ impl GameBoy {
         } else {
             self.apu_sound_output[2] = 0;
         }
-        // Add channel 3 to apu buffer left
-        if self.io[0x25] & 0b0100_0000 > 0 {
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2] +=
-                ((self.apu_sound_output[2] as u16) * (16 * ((self.io[0x24] >> 4) & 0x07) as u16))
-                    / VOL_DIV;
-        }
-        // Add channel 3 to apu buffer right
-        if self.io[0x25] & 0b0000_0100 > 0 {
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2 + 1] +=
-                ((self.apu_sound_output[2] as u16) * (16 * ((self.io[0x24]) & 0x07) as u16)) / VOL_DIV;
-        }
+        self.mix_channel(2);
     }
 
     fn handle_apu_channel_4(&mut self) {
@@ -206,16 +193,40 @@ impl GameBoy {
         } else {
             self.apu_sound_output[3] = 0;
         }
-        // Add channel 4 to apu buffer left
-        if self.io[0x25] & 0b1000_0000 > 0 {
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2] +=
-                ((self.apu_sound_output[3] as u16) * (16 * ((self.io[0x24] >> 4) & 0x07) as u16))
-                    / VOL_DIV;
+        self.mix_channel(3);
+    }
+
+    fn mix_channel(&mut self, channel: usize) {
+        // The accurate path mixes all channels at once
+        if self.audio_mode != AudioMode::Fast {
+            return;
         }
-        // Add channel 4 to apu buffer right
-        if self.io[0x25] & 0b0000_1000 > 0 {
-            self.apu_buffer[self.apu_buffer_to_use][(self.apu_buffer_write_index >> 6) * 2 + 1] +=
-                ((self.apu_sound_output[3] as u16) * (16 * ((self.io[0x24]) & 0x07) as u16)) / VOL_DIV;
+
+        let index = (self.apu_buffer_write_index >> 6) * 2;
+        let output = self.apu_sound_output[channel] as u16;
+        let left = if self.io[0x25] & (0x10 << channel) > 0 {
+            (output * (16 * ((self.io[0x24] >> 4) & 0x07) as u16)) / VOL_DIV
+        } else {
+            0
+        };
+        let right = if self.io[0x25] & (0x01 << channel) > 0 {
+            (output * (16 * (self.io[0x24] & 0x07) as u16)) / VOL_DIV
+        } else {
+            0
+        };
+
+        // Add the channel to the apu buffer
+        self.apu_buffer[self.apu_buffer_to_use][index] += left;
+        self.apu_buffer[self.apu_buffer_to_use][index + 1] += right;
+
+        // And keep it separately if it's being recorded on its own
+        if let Some(buffers) = self
+            .audio_recorder
+            .as_mut()
+            .and_then(|recorder| recorder.channel_buffers.as_mut())
+        {
+            buffers[channel][index] += left;
+            buffers[channel][index + 1] += right;
         }
     }
 
@@ -0,0 +1,172 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::super::GameBoy;
+
+/// A 16-bit PCM stereo WAV file. The sizes in the header are filled in when it's finished.
+struct WavWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    data_bytes: u32,
+    finished: bool,
+}
+
+impl WavWriter {
+    fn create(path: &Path, sample_rate: u32) -> io::Result<WavWriter> {
+        let mut writer = WavWriter {
+            file: BufWriter::new(File::create(path)?),
+            sample_rate,
+            data_bytes: 0,
+            finished: false,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let channels = 2_u16;
+        let block_align = channels * 2;
+        self.file.write_all(b"RIFF")?;
+        self.file.write_all(&(36 + self.data_bytes).to_le_bytes())?;
+        self.file.write_all(b"WAVEfmt ")?;
+        self.file.write_all(&16_u32.to_le_bytes())?;
+        self.file.write_all(&1_u16.to_le_bytes())?; // PCM
+        self.file.write_all(&channels.to_le_bytes())?;
+        self.file.write_all(&self.sample_rate.to_le_bytes())?;
+        self.file
+            .write_all(&(self.sample_rate * block_align as u32).to_le_bytes())?;
+        self.file.write_all(&block_align.to_le_bytes())?;
+        self.file.write_all(&16_u16.to_le_bytes())?;
+        self.file.write_all(b"data")?;
+        self.file.write_all(&self.data_bytes.to_le_bytes())
+    }
+
+    fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes())?;
+            self.data_bytes += 2;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        // Go back and fill in the sizes now that we know them
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+pub(in super::super) struct AudioRecorder {
+    mixed: WavWriter,
+    channels: Option<[WavWriter; 4]>,
+    // What each channel added to the mix on the fast path, interleaved stereo like the APU buffer
+    pub(in super::super) channel_buffers: Option<[[u16; 512]; 4]>,
+}
+
+impl AudioRecorder {
+    fn write_block(&mut self, mixed: &[i16], channels: Option<&[Vec<i16>; 4]>) -> io::Result<()> {
+        self.mixed.write_samples(mixed)?;
+        let Some(writers) = &mut self.channels else {
+            return Ok(());
+        };
+
+        // The accurate path hands us the channels, the fast path collects them in the mixer
+        match (channels, &mut self.channel_buffers) {
+            (Some(channels), _) => {
+                for (writer, samples) in writers.iter_mut().zip(channels) {
+                    writer.write_samples(samples)?;
+                }
+            }
+            (None, Some(buffers)) => {
+                for (writer, buffer) in writers.iter_mut().zip(buffers.iter_mut()) {
+                    let samples: Vec<i16> = buffer.iter().map(|&sample| sample as i16).collect();
+                    writer.write_samples(&samples)?;
+                    buffer.fill(0);
+                }
+            }
+            (None, None) => (),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.mixed.finish()?;
+        for channel in self.channels.iter_mut().flatten() {
+            channel.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl GameBoy {
+    /// Starts writing the audio output to a 16-bit stereo WAV file at `audio_sample_rate` Hz.
+    /// With `separate_channels`, each of the four channels also gets its own file next to it,
+    /// so recording to `song.wav` also writes `song.ch1.wav` through `song.ch4.wav`. The channel
+    /// files hold exactly what that channel added to the mix, panning and master volume included.
+    pub fn start_audio_recording(
+        &mut self,
+        path: impl AsRef<Path>,
+        separate_channels: bool,
+    ) -> io::Result<()> {
+        let path = path.as_ref();
+        let sample_rate = self.audio_sample_rate();
+        let mixed = WavWriter::create(path, sample_rate)?;
+        let channels = match separate_channels {
+            true => {
+                let create = |number: usize| {
+                    WavWriter::create(&path.with_extension(format!("ch{number}.wav")), sample_rate)
+                };
+                Some([create(1)?, create(2)?, create(3)?, create(4)?])
+            }
+            false => None,
+        };
+
+        self.audio_recorder = Some(AudioRecorder {
+            mixed,
+            channel_buffers: channels.as_ref().map(|_| [[0; 512]; 4]),
+            channels,
+        });
+        Ok(())
+    }
+
+    /// Stops recording and finishes the WAV files
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        match self.audio_recorder.take() {
+            Some(mut recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording_audio(&self) -> bool {
+        self.audio_recorder.is_some()
+    }
+
+    pub(in super::super) fn record_audio_block(
+        &mut self,
+        mixed: &[i16],
+        channels: Option<&[Vec<i16>; 4]>,
+    ) {
+        let Some(recorder) = &mut self.audio_recorder else {
+            return;
+        };
+
+        // Don't keep trying if the disk is full
+        if let Err(e) = recorder.write_block(mixed, channels) {
+            println!("Unable to write audio recording, stopping it: {e}");
+            self.audio_recorder = None;
+        }
+    }
+}
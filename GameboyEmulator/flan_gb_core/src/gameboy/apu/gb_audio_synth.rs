@@ -0,0 +1,289 @@
+use std::f32::consts::PI;
+
+use super::super::GameBoy;
+
+// The APU ticks at 2097152 Hz
+const APU_CLOCK_RATE: f64 = 2097152.0;
+// Every change in level is spread out over this many output samples
+const STEP_TAPS: usize = 16;
+// And placed between two output samples with this much precision
+const STEP_PHASES: usize = 32;
+// Samples are finished in blocks of this many per side
+const BLOCK_SAMPLES: usize = 512;
+// What one channel at full volume comes out as
+const CHANNEL_SCALE: f32 = 6000.0;
+
+// Sample rates the accurate path can run at
+const MIN_SAMPLE_RATE: u32 = 8000;
+const MAX_SAMPLE_RATE: u32 = 192000;
+
+/// Sample rate of the fast audio path, in Hz
+pub const FAST_AUDIO_SAMPLE_RATE: u32 = 32768;
+
+/// How the APU turns the four channels into samples
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AudioMode {
+    /// Band-limited synthesis at any sample rate, mixed and filtered like the real hardware
+    #[default]
+    Accurate,
+    /// The channels averaged into 32768 Hz samples. Cheaper, but it aliases.
+    Fast,
+}
+
+// One channel, as heard on the left and right
+struct SynthChannel {
+    level: [f32; 2],
+    deltas: [Vec<f32>; 2],
+    sum: [f32; 2],
+    capacitor: [f32; 2],
+}
+
+pub(in super::super) struct AudioSynth {
+    kernel: Vec<[f32; STEP_TAPS]>,
+    channels: [SynthChannel; 4],
+    sample_rate: u32,
+    rate_adjustment: f64,
+    samples_per_tick: f64,
+    position: f64,
+    mixer_inputs: u64,
+    dmg_charge_factor: f32,
+    cgb_charge_factor: f32,
+}
+
+impl AudioSynth {
+    pub(in super::super) fn new(sample_rate: u32) -> AudioSynth {
+        let new_channel = || SynthChannel {
+            level: [0.0; 2],
+            deltas: [
+                vec![0.0; BLOCK_SAMPLES + STEP_TAPS],
+                vec![0.0; BLOCK_SAMPLES + STEP_TAPS],
+            ],
+            sum: [0.0; 2],
+            capacitor: [0.0; 2],
+        };
+
+        // The high-pass filter's capacitor loses this much charge per output sample
+        let ticks_per_sample = 4194304.0 / sample_rate as f64;
+        AudioSynth {
+            kernel: step_kernel(),
+            channels: [new_channel(), new_channel(), new_channel(), new_channel()],
+            sample_rate,
+            rate_adjustment: 1.0,
+            samples_per_tick: sample_rate as f64 / APU_CLOCK_RATE,
+            position: 0.0,
+            mixer_inputs: u64::MAX,
+            dmg_charge_factor: 0.999958_f64.powf(ticks_per_sample) as f32,
+            cgb_charge_factor: 0.998943_f64.powf(ticks_per_sample) as f32,
+        }
+    }
+
+    pub(in super::super) fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub(in super::super) fn reset(&mut self) {
+        let rate_adjustment = self.rate_adjustment;
+        *self = AudioSynth::new(self.sample_rate);
+        self.set_rate_adjustment(rate_adjustment);
+    }
+
+    pub(in super::super) fn set_rate_adjustment(&mut self, rate_adjustment: f64) {
+        self.rate_adjustment = rate_adjustment;
+        self.samples_per_tick = self.sample_rate as f64 / APU_CLOCK_RATE * rate_adjustment;
+    }
+
+    /// Sets what a channel outputs on each side right now, from -1.0 to 1.0
+    pub(in super::super) fn set_level(&mut self, channel: usize, left: f32, right: f32) {
+        let index = self.position as usize;
+        let phase = (self.position.fract() * STEP_PHASES as f64) as usize;
+        let channel = &mut self.channels[channel];
+
+        for (side, level) in [left, right].into_iter().enumerate() {
+            let delta = level - channel.level[side];
+            if delta == 0.0 {
+                continue;
+            }
+            channel.level[side] = level;
+
+            // Add a band-limited step instead of a sharp one, so it doesn't alias
+            for (tap, weight) in self.kernel[phase].iter().enumerate() {
+                channel.deltas[side][index + tap] += delta * weight;
+            }
+        }
+    }
+
+    /// Moves forward by one APU tick, returns true when a block of samples is ready
+    pub(in super::super) fn tick(&mut self) -> bool {
+        self.position += self.samples_per_tick;
+        self.position >= BLOCK_SAMPLES as f64
+    }
+
+    /// Takes the finished block as interleaved stereo samples, one set per channel
+    pub(in super::super) fn finish_block(&mut self, cgb_mode: bool) -> [Vec<i16>; 4] {
+        let charge_factor = match cgb_mode {
+            true => self.cgb_charge_factor,
+            false => self.dmg_charge_factor,
+        };
+        self.position -= BLOCK_SAMPLES as f64;
+
+        let mut blocks: [Vec<i16>; 4] = Default::default();
+        for (channel, block) in self.channels.iter_mut().zip(blocks.iter_mut()) {
+            *block = vec![0; BLOCK_SAMPLES * 2];
+            for side in 0..2 {
+                for (index, delta) in channel.deltas[side][..BLOCK_SAMPLES].iter().enumerate() {
+                    channel.sum[side] += delta;
+
+                    // The capacitor on the output removes any DC offset, like on the real hardware
+                    let output = channel.sum[side] - channel.capacitor[side];
+                    channel.capacitor[side] = channel.sum[side] - output * charge_factor;
+
+                    block[index * 2 + side] = (output * CHANNEL_SCALE).round() as i16;
+                }
+
+                // Steps that reach into the next block carry over
+                channel.deltas[side].copy_within(BLOCK_SAMPLES.., 0);
+                channel.deltas[side][STEP_TAPS..].fill(0.0);
+            }
+        }
+        blocks
+    }
+}
+
+// A windowed sinc impulse for each phase, which adds up to a band-limited step
+fn step_kernel() -> Vec<[f32; STEP_TAPS]> {
+    // Cut off a little below half the output sample rate
+    let cutoff = 0.45;
+    let half_width = (STEP_TAPS / 2) as f32;
+
+    (0..STEP_PHASES)
+        .map(|phase| {
+            let offset = phase as f32 / STEP_PHASES as f32;
+            let mut taps = [0.0; STEP_TAPS];
+            for (tap, weight) in taps.iter_mut().enumerate() {
+                let x = tap as f32 - half_width + 1.0 - offset;
+                let sinc = match x == 0.0 {
+                    true => 2.0 * cutoff,
+                    false => (2.0 * PI * cutoff * x).sin() / (PI * x),
+                };
+                let window = 0.42
+                    + 0.5 * (PI * x / half_width).cos()
+                    + 0.08 * (2.0 * PI * x / half_width).cos();
+                *weight = sinc * window;
+            }
+
+            // Every step should end up exactly as high as it was asked to be
+            let total: f32 = taps.iter().sum();
+            taps.iter_mut().for_each(|weight| *weight /= total);
+            taps
+        })
+        .collect()
+}
+
+impl GameBoy {
+    /// Switches between the accurate and the fast audio path. Samples that weren't taken yet
+    /// are dropped, and a recording in progress is finished, since the sample rate may change.
+    pub fn set_audio_mode(&mut self, mode: AudioMode) {
+        self.audio_mode = mode;
+        self.restart_audio_output();
+    }
+
+    pub fn audio_mode(&self) -> AudioMode {
+        self.audio_mode
+    }
+
+    /// Sets the sample rate of the accurate audio path, usually to the one of the audio device.
+    /// The fast path always runs at `FAST_AUDIO_SAMPLE_RATE`.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.audio_synth = AudioSynth::new(sample_rate.clamp(MIN_SAMPLE_RATE, MAX_SAMPLE_RATE));
+        self.restart_audio_output();
+    }
+
+    /// Makes the accurate path produce a little more (above 1.0) or fewer (below 1.0) samples for
+    /// the same amount of emulated time, so a frontend can keep its audio buffer from running dry
+    /// or filling up. Clamped to 0.5..=2.0. The fast path ignores this.
+    pub fn set_audio_rate_adjustment(&mut self, rate_adjustment: f64) {
+        self.audio_synth
+            .set_rate_adjustment(rate_adjustment.clamp(0.5, 2.0));
+    }
+
+    /// The sample rate of what `take_audio_samples` returns, in Hz
+    pub fn audio_sample_rate(&self) -> u32 {
+        match self.audio_mode {
+            AudioMode::Accurate => self.audio_synth.sample_rate(),
+            AudioMode::Fast => FAST_AUDIO_SAMPLE_RATE,
+        }
+    }
+
+    fn restart_audio_output(&mut self) {
+        self.apu_output.clear();
+        self.audio_synth.reset();
+        if let Err(e) = self.stop_audio_recording() {
+            println!("Unable to finish audio recording: {e}");
+        }
+    }
+
+    pub(in super::super) fn synthesize_audio(&mut self) {
+        // Most ticks nothing changes, so only mix when something did
+        let dac_enabled = [
+            self.io[0x12] & 0xF8 != 0,
+            self.io[0x17] & 0xF8 != 0,
+            self.io[0x1A] & 0x80 != 0,
+            self.io[0x21] & 0xF8 != 0,
+        ];
+        let mixer_inputs = u64::from_le_bytes([
+            self.apu_sound_output[0],
+            self.apu_sound_output[1],
+            self.apu_sound_output[2],
+            self.apu_sound_output[3],
+            self.io[0x24],
+            self.io[0x25],
+            dac_enabled
+                .iter()
+                .fold(0, |bits, &enabled| bits << 1 | enabled as u8),
+            0,
+        ]);
+        if mixer_inputs != self.audio_synth.mixer_inputs {
+            self.audio_synth.mixer_inputs = mixer_inputs;
+            self.mix_synth_channels(dac_enabled);
+        }
+
+        if self.audio_synth.tick() {
+            let channels = self.audio_synth.finish_block(self.cgb_mode);
+
+            // The mix is the sum of the channels, so recordings of the channels add up to it exactly
+            let mixed: Vec<i16> = (0..BLOCK_SAMPLES * 2)
+                .map(|index| {
+                    let sum: i32 = channels.iter().map(|block| block[index] as i32).sum();
+                    sum.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+                })
+                .collect();
+            self.apu_output.extend_from_slice(&mixed);
+            self.record_audio_block(&mixed, Some(&channels));
+        }
+    }
+
+    fn mix_synth_channels(&mut self, dac_enabled: [bool; 4]) {
+        // NR50 sets the volume of each side from 1/8 to 8/8
+        let left_volume = (((self.io[0x24] >> 4) & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((self.io[0x24] & 0x07) + 1) as f32 / 8.0;
+
+        for (channel, dac_enabled) in dac_enabled.into_iter().enumerate() {
+            // The DACs turn 0..=15 into -1.0..=1.0, and output nothing at all while they're off
+            let analog = match dac_enabled {
+                true => self.apu_sound_output[channel] as f32 / 60.0 - 1.0,
+                false => 0.0,
+            };
+
+            // NR51 picks which sides hear the channel
+            let left = match self.io[0x25] & (0x10 << channel) > 0 {
+                true => analog * left_volume,
+                false => 0.0,
+            };
+            let right = match self.io[0x25] & (0x01 << channel) > 0 {
+                true => analog * right_volume,
+                false => 0.0,
+            };
+            self.audio_synth.set_level(channel, left, right);
+        }
+    }
+}
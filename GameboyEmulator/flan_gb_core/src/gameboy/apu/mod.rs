@@ -0,0 +1,3 @@
+pub mod gb_apu;
+pub mod gb_audio_recorder;
+pub mod gb_audio_synth;
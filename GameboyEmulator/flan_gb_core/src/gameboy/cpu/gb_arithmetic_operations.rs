@@ -164,7 +164,6 @@ impl GameBoy {
 
     pub(in super::super) fn cp_8_8(&mut self, a: u8, b: u8) {
         // This instruction is a SUB instruction without changing the actual register, so A is not mutable
-        let a = a;
         self.sub_8_8(a, b);
     }
 }
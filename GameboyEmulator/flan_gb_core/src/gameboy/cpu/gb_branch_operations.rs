@@ -1,4 +1,5 @@
 use super::super::GameBoy;
+use crate::gameboy::debug::gb_debugger::WatchKind;
 
 impl GameBoy {
     pub(in super::super) fn jump_absolute(&mut self, address: u16) {
@@ -23,9 +24,9 @@ impl GameBoy {
 
     pub(in super::super) fn pop_stack(&mut self) -> u16 {
         let mut popped_value = 0x00;
-        popped_value |= self.fetch_byte_from_memory(self.sp) as u16;
+        popped_value |= self.fetch_byte_from_memory(self.sp, WatchKind::Read) as u16;
         self.sp += 1;
-        popped_value |= (self.fetch_byte_from_memory(self.sp) as u16) << 8;
+        popped_value |= (self.fetch_byte_from_memory(self.sp, WatchKind::Read) as u16) << 8;
         self.sp += 1;
         popped_value
     }
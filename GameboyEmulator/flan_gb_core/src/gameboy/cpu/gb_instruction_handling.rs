@@ -0,0 +1,244 @@
+use crate::gameboy::FlagMask;
+
+use super::super::debug::gb_debugger::WatchKind;
+use super::super::misc::gb_poison::Ram;
+use super::super::GameBoy;
+
+impl GameBoy {
+    /// Runs the machine until the PPU enters V-blank, or for a short while if the LCD is off.
+    /// Returns early if the debugger pauses the machine.
+    pub fn run_frame(&mut self) {
+        while !self.run_frame_for(u64::MAX) {}
+    }
+
+    /// Like `run_frame`, but stops after `dots` dots (at 4 MiHz, even in double speed mode) to
+    /// let the input be updated partway through the frame. Returns true once the frame is done,
+    /// or the debugger paused the machine.
+    pub fn run_frame_for(&mut self, dots: u64) -> bool {
+        let end = self.elapsed_dots.saturating_add(dots);
+        while self.elapsed_dots < end {
+            let prev = self.ppu_ly;
+            self.step();
+
+            if self.debugger.paused.is_some() {
+                return true;
+            }
+            if prev != self.ppu_ly && self.ppu_ly == 144 {
+                return true;
+            }
+            if (self.io[0x40] & 0x80 == 0) && (self.timer_div < 3) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Advances the whole machine by one CPU cycle
+    pub fn step(&mut self) {
+        // Everything stands still while the debugger has us paused
+        if self.debugger.paused.is_some() {
+            return;
+        }
+
+        // Give the debugger a chance to pause before the next instruction runs. This happens before anything else in
+        // the step, so the step that runs after resuming doesn't tick the timer or the PPU a second time.
+        let instruction_starts =
+            !self.is_stopped && !self.is_halted && self.curr_cycles_to_wait <= 1;
+        if self.debugger.enabled && instruction_starts && self.debugger_should_break() {
+            return;
+        }
+
+        self.process_next_instruction();
+        self.run_serial_cycle();
+        //self.print_reg_state();
+
+        // In double speed mode the PPU, APU and RTC keep running at the same speed, so they get half as much time per CPU cycle
+        self.run_ppu_cycle();
+        if !self.double_speed {
+            self.run_ppu_cycle();
+        }
+        self.elapsed_dots += if self.double_speed { 1 } else { 2 };
+        self.double_speed_skip_cycle = self.double_speed && !self.double_speed_skip_cycle;
+        if !self.double_speed_skip_cycle {
+            self.run_apu_cycle();
+            self.mapper.run_cycle();
+        }
+
+        if self.oam_dma_counter > 0 {
+            let offset = 160 - self.oam_dma_counter as usize;
+            self.oam[offset] =
+                self.fetch_byte_from_memory(self.oam_dma_source + offset as u16, WatchKind::Read);
+            self.mark_dma_written(Ram::Oam, offset);
+            self.oam_dma_counter -= 1;
+        }
+    }
+
+    pub(in super::super) fn process_next_instruction(&mut self) {
+        // In STOP mode the CPU and timer sleep until a button is pressed
+        if self.is_stopped {
+            return;
+        }
+
+        // Update timer
+        self.handle_timer();
+
+        // Wait for previous instruction to finish
+        self.new_instruction_tick = false;
+        if self.curr_cycles_to_wait > 1 {
+            self.curr_cycles_to_wait -= 1;
+            return;
+        }
+        if self.curr_cycles_to_wait > 0 {
+            self.curr_cycles_to_wait -= 1;
+            self.new_instruction_tick = true;
+        }
+
+        // Interrupts
+        let was_halted = self.is_halted;
+        let pc_before_interrupts = self.pc;
+        self.handle_interrupts();
+
+        // If halted, stop here
+        if self.is_halted {
+            return;
+        }
+
+        // Jumping to an interrupt handler or waking up from HALT already took up this step, so the debugger can
+        // pause at the new instruction here
+        if self.debugger.enabled
+            && (was_halted || self.pc != pc_before_interrupts)
+            && self.debugger_should_break()
+        {
+            return;
+        }
+
+        if self.trace_log.is_some() {
+            self.write_trace_line();
+        }
+
+        self.execute_next_instruction();
+    }
+
+    /// Runs exactly one instruction on the CPU alone, without the timer, interrupts or PPU.
+    /// Returns how many M-cycles it took.
+    pub fn step_instruction(&mut self) -> u32 {
+        self.curr_cycles_to_wait = 0;
+        self.execute_next_instruction();
+        self.curr_cycles_to_wait
+    }
+
+    fn execute_next_instruction(&mut self) {
+        // Read byte from PC
+        self.instruction_pc = self.pc;
+        let opcode = self.fetch_next_byte_from_pc();
+        self.last_opcode = opcode;
+
+        // Pass it to a bunch of functions, let them handle it. If none of them handle it, this is an invalid opcode, and we should hang.
+        if self.handle_misc_instructions(opcode) {
+            self.last_opcode_cycles = self.curr_cycles_to_wait;
+            return;
+        }
+        if self.handle_load_instructions(opcode) {
+            self.last_opcode_cycles = self.curr_cycles_to_wait;
+            return;
+        }
+        if self.handle_arithmetic_instructions(opcode) {
+            self.last_opcode_cycles = self.curr_cycles_to_wait;
+            return;
+        }
+        if self.handle_branch_instructions(opcode) {
+            self.last_opcode_cycles = self.curr_cycles_to_wait;
+            return;
+        }
+        if self.handle_incdec_instructions(opcode) {
+            self.last_opcode_cycles = self.curr_cycles_to_wait;
+            return;
+        }
+
+        // If we get here, we have an instruction that we don't know how to process
+        println!("--Opcode ${:02X} not implemented!--", opcode);
+        self.print_reg_state();
+        panic!();
+    }
+
+    pub(in super::super) fn handle_misc_instructions(&mut self, opcode: u8) -> bool {
+        // These are all the special cases that don't fit neatly into one category
+        match opcode {
+            0x00 => return true, // NOP - no operation
+            0x07 => {
+                self.reg_a = self.rlc(self.reg_a);
+                self.reg_f &= FlagMask::Carry as u8;
+            }
+            0x0F => {
+                self.reg_a = self.rrc(self.reg_a);
+                self.reg_f &= FlagMask::Carry as u8;
+            }
+            0x10 => {
+                self.fetch_next_byte_from_pc();
+
+                // On CGB, STOP is how games switch between normal and double speed, after requesting it in KEY1
+                if self.cgb_mode && self.io[0x4D] & 0x01 > 0 {
+                    self.io[0x4D] &= !0x01;
+                    self.double_speed = !self.double_speed;
+                    self.double_speed_skip_cycle = false;
+                    self.timer_div = 0;
+                    self.curr_cycles_to_wait += 2050;
+                } else if self.joypad_lines() == 0x0F {
+                    // Otherwise it sleeps until a button is pressed, unless one already is
+                    self.is_stopped = true;
+                    self.timer_div = 0;
+                }
+            } // STOP
+            0x17 => {
+                self.reg_a = self.rl(self.reg_a);
+                self.reg_f &= FlagMask::Carry as u8;
+            }
+            0x1F => {
+                self.reg_a = self.rr(self.reg_a);
+                self.reg_f &= FlagMask::Carry as u8;
+            }
+            0x76 => self.is_halted = true,
+            0xCB => {
+                self.handle_prefixed_instructions(opcode);
+            } // CB - prefixed instructions mostly for bit shifting, setting, and clearing
+            0xF3 => self.ime = 0,
+            0xFB => self.ime = 1,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A cartridge that turns the LCD on and then spins in a loop
+    fn test_game_boy() -> GameBoy {
+        let mut rom = vec![0x00; 0x8000];
+        // LD A, $91; LDH [$FF40], A; JR -2
+        rom[0x100..0x106].copy_from_slice(&[0x3E, 0x91, 0xE0, 0x40, 0x18, 0xFE]);
+        let mut game_boy = GameBoy::with_power_on_seed(1);
+        assert!(game_boy.load_rom(rom));
+        game_boy.skip_boot_rom();
+        game_boy
+    }
+
+    #[test]
+    fn frames_in_slices_match_whole_frames() {
+        let mut whole = test_game_boy();
+        let mut sliced = test_game_boy();
+        // Until the LCD is on, frames end early
+        whole.run_frame();
+        sliced.run_frame();
+        for _ in 0..3 {
+            whole.run_frame();
+            let mut slices = 1;
+            while !sliced.run_frame_for(1000) {
+                slices += 1;
+            }
+            assert!(slices > 1, "frame ran in {slices} slice");
+            assert!(sliced.save_state() == whole.save_state());
+        }
+    }
+}
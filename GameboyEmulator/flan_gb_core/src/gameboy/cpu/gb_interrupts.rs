@@ -63,6 +63,11 @@ impl GameBoy {
                 self.push_stack(self.pc);
                 self.jump_absolute(0x0060);
             }
+
+            // IME only gets cleared when a handler was called
+            if self.debugger.enabled && self.ime == 0 {
+                self.debugger_interrupt_taken();
+            }
         }
     }
 
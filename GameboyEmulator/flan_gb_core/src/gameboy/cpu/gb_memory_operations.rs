@@ -1,8 +1,30 @@
+use crate::gameboy::debug::gb_debugger::WatchKind;
 use crate::gameboy::GameBoy;
 
 impl GameBoy {
-    pub(in super::super) fn fetch_byte_from_memory(&mut self, address: u16) -> u8 {
+    // Reads memory as the CPU, taking a cycle. Opcode and operand fetches pass Execute, which doesn't trip any
+    // watchpoints since execute watchpoints are checked before the instruction starts.
+    pub(in super::super) fn fetch_byte_from_memory(
+        &mut self,
+        address: u16,
+        access: WatchKind,
+    ) -> u8 {
         self.curr_cycles_to_wait += 1;
+        if self.debugger.enabled && access != WatchKind::Execute {
+            self.check_watchpoints(address, access);
+        }
+        if self.written_memory.is_some() {
+            self.check_ram_read(address);
+        }
+        self.peek_byte_from_memory(address)
+    }
+
+    // Reads memory the same way the CPU would, but without taking any time or tripping watchpoints
+    pub(in super::super) fn peek_byte_from_memory(&self, address: u16) -> u8 {
+        if let Some(memory) = &self.test_bus {
+            return memory[address as usize];
+        }
+
         match address {
             // ROM bank 0
             0x0000..=0x3FFF => {
@@ -11,7 +33,7 @@ impl GameBoy {
                 }
 
                 if !self.rom.is_empty() {
-                    self.rom[(address as usize) % self.rom.len()]
+                    self.rom[self.mapper.rom_offset(address) % self.rom.len()]
                 } else {
                     0xFF
                 }
@@ -19,9 +41,7 @@ impl GameBoy {
             // ROM bank 1 or higher
             0x4000..=0x7FFF => {
                 if !self.rom.is_empty() {
-                    self.rom[(0x4000 * (self.curr_rom_bank as usize)
-                        + ((address & 0x3FFF) as usize))
-                        % self.rom.len()]
+                    self.rom[self.mapper.rom_offset(address) % self.rom.len()]
                 } else {
                     0xFF
                 }
@@ -30,33 +50,21 @@ impl GameBoy {
             0x8000..=0x9FFF => {
                 // Make sure this only returns the right value when PPU is unlocked, otherwise return 0xFF
                 if self.ppu_mode != 3 {
-                    self.vram[(address & 0x1FFF) as usize]
+                    self.vram[self.vram_bank * 0x2000 + (address & 0x1FFF) as usize]
                 } else {
                     0xFF
                 }
             }
             // External RAM
-            0xA000..=0xBFFF => {
-                if self.eram_chip_enabled {
-                    self.eram[(address as usize & 0x1FFF) + 0x2000 * self.curr_eram_bank as usize]
-                } else {
-                    0xFF
-                }
-            }
+            0xA000..=0xBFFF => self.mapper.read_ram(&self.eram, address),
             // WRAM bank 0
-            0xC000..=0xCFFF => self.wram[(address & 0x1FFF) as usize],
+            0xC000..=0xCFFF => self.wram[(address & 0x0FFF) as usize],
             // WRAM bank 1 or higher
-            0xD000..=0xDFFF => {
-                // TODO: implement bank switching
-                self.wram[(address & 0x1FFF) as usize]
-            }
+            0xD000..=0xDFFF => self.wram[self.wram_bank * 0x1000 + (address & 0x0FFF) as usize],
             // WRAM bank 0 (mirror)
-            0xE000..=0xEFFF => self.wram[(address & 0x1FFF) as usize],
+            0xE000..=0xEFFF => self.wram[(address & 0x0FFF) as usize],
             // WRAM bank 1 or higher (mirror)
-            0xF000..=0xFDFF => {
-                // TODO: implement bank switching
-                self.wram[(address & 0x1FFF) as usize]
-            }
+            0xF000..=0xFDFF => self.wram[self.wram_bank * 0x1000 + (address & 0x0FFF) as usize],
             // OAM sprite attribute table
             0xFE00..=0xFE9F => {
                 // Only return the byte if the PPU is not accessing this memory
@@ -80,54 +88,45 @@ impl GameBoy {
 
     pub(in super::super) fn store_byte_to_memory(&mut self, address: u16, value: u8) {
         self.curr_cycles_to_wait += 1;
+        if self.debugger.enabled {
+            self.check_watchpoints(address, WatchKind::Write);
+        }
+        if self.written_memory.is_some() {
+            self.mark_ram_written(address);
+        }
+        self.poke_byte_to_memory(address, value);
+    }
+
+    // Writes memory the same way the CPU would, but without taking any time or tripping watchpoints
+    pub(in super::super) fn poke_byte_to_memory(&mut self, address: u16, value: u8) {
+        if let Some(memory) = &mut self.test_bus {
+            memory[address as usize] = value;
+            return;
+        }
+
         match address {
-            // ROM bank 0
-            0x0000..=0x3FFF => {
-                // TODO: implement mapper stuff
-                if (0x0000..=0x1FFF).contains(&address) {
-                    self.eram_chip_enabled = value & 0x0F == 0x0A;
-                }
-                if (0x2000..=0x3FFF).contains(&address) {
-                    self.curr_rom_bank = value;
-                    if self.curr_rom_bank == 0 {
-                        self.curr_rom_bank = 1;
-                    }
-                }
-            }
-            // ROM bank 1 or higher
-            0x4000..=0x7FFF => {
-                if (0x4000..=0x5FFF).contains(&address) {
-                    self.curr_eram_bank = (value as usize % (self.eram.len() / 8192)) as u8;
-                }
-            }
+            // ROM area, writes here go to the mapper registers
+            0x0000..=0x7FFF => self.mapper.write_register(address, value),
             // VRAM bank 0 or 1
             0x8000..=0x9FFF => {
                 // Only store the value if the PPU is not reading from VRAM
                 if self.ppu_mode != 3 {
-                    self.vram[(address & 0x1FFF) as usize] = value;
+                    self.vram[self.vram_bank * 0x2000 + (address & 0x1FFF) as usize] = value;
                 }
             }
             // External RAM
-            0xA000..=0xBFFF => {
-                if self.eram_chip_enabled {
-                    self.eram
-                        [(address as usize & 0x1FFF) + 0x2000 * self.curr_eram_bank as usize] =
-                        value;
-                } //else ignore
-            }
+            0xA000..=0xBFFF => self.mapper.write_ram(&mut self.eram, address, value),
             // WRAM bank 0
-            0xC000..=0xCFFF => self.wram[(address & 0x1FFF) as usize] = value,
+            0xC000..=0xCFFF => self.wram[(address & 0x0FFF) as usize] = value,
             // WRAM bank 1 or higher
             0xD000..=0xDFFF => {
-                // TODO: implement bank switching
-                self.wram[(address & 0x1FFF) as usize] = value;
+                self.wram[self.wram_bank * 0x1000 + (address & 0x0FFF) as usize] = value
             }
             // WRAM bank 0 (mirror)
-            0xE000..=0xEFFF => self.wram[(address & 0x1FFF) as usize] = value,
+            0xE000..=0xEFFF => self.wram[(address & 0x0FFF) as usize] = value,
             // WRAM bank 1 or higher (mirror)
             0xF000..=0xFDFF => {
-                // TODO: implement bank switching
-                self.wram[(address & 0x1FFF) as usize] = value;
+                self.wram[self.wram_bank * 0x1000 + (address & 0x0FFF) as usize] = value
             }
             // OAM sprite attribute table
             0xFE00..=0xFE9F => {
@@ -158,21 +157,21 @@ impl GameBoy {
     }
 
     pub(in super::super) fn fetch_next_byte_from_pc(&mut self) -> u8 {
-        let byte = self.fetch_byte_from_memory(self.pc);
+        let byte = self.fetch_byte_from_memory(self.pc, WatchKind::Execute);
         self.pc += 1;
         byte
     }
     pub(in super::super) fn fetch_next_short_from_pc(&mut self) -> u16 {
-        let byte1 = self.fetch_byte_from_memory(self.pc) as u16;
+        let byte1 = self.fetch_byte_from_memory(self.pc, WatchKind::Execute) as u16;
         self.pc += 1;
-        let byte2 = self.fetch_byte_from_memory(self.pc) as u16;
+        let byte2 = self.fetch_byte_from_memory(self.pc, WatchKind::Execute) as u16;
         self.pc += 1;
         byte1 + (byte2 << 8)
     }
     pub(in super::super) fn fetch_short_from_memory(&mut self, address: u16) -> u16 {
-        let byte1 = self.fetch_byte_from_memory(address) as u16;
+        let byte1 = self.fetch_byte_from_memory(address, WatchKind::Read) as u16;
         let address = address + 1;
-        let byte2 = self.fetch_byte_from_memory(address) as u16;
+        let byte2 = self.fetch_byte_from_memory(address, WatchKind::Read) as u16;
         byte1 + (byte2 << 8)
     }
 
@@ -183,6 +182,6 @@ impl GameBoy {
 
     pub(in super::super) fn load8_from_pointer16(&mut self, h: u8, l: u8) -> u8 {
         let address = (h as u16) << 8 | (l as u16);
-        self.fetch_byte_from_memory(address)
+        self.fetch_byte_from_memory(address, WatchKind::Read)
     }
 }
@@ -1,3 +1,4 @@
+use crate::gameboy::debug::gb_debugger::WatchKind;
 use crate::GameBoy;
 
 use super::super::FlagMask;
@@ -133,7 +134,7 @@ impl GameBoy {
             5 => self.reg_l,
             6 => {
                 let address = (self.reg_h as u16) << 8 | self.reg_l as u16;
-                self.fetch_byte_from_memory(address)
+                self.fetch_byte_from_memory(address, WatchKind::Read)
             }
             7 => self.reg_a,
             _ => panic!(),
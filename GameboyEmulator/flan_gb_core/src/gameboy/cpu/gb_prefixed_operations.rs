@@ -4,7 +4,7 @@ use super::super::GameBoy;
 impl GameBoy {
     pub(in super::super) fn rlc(&mut self, value: u8) -> u8 {
         // Shift
-        let result = value << 1 | value >> 7;
+        let result = value.rotate_left(1);
 
         // Fix flags
         self.reg_f = 0;
@@ -18,7 +18,7 @@ impl GameBoy {
     }
     pub(in super::super) fn rrc(&mut self, value: u8) -> u8 {
         // Shift
-        let result = value >> 1 | value << 7;
+        let result = value.rotate_right(1);
 
         // Fix flags
         self.reg_f = 0;
@@ -94,7 +94,7 @@ impl GameBoy {
     }
     pub(in super::super) fn swap(&mut self, value: u8) -> u8 {
         // Swap nibbles
-        let result = value >> 4 | value << 4;
+        let result = value.rotate_left(4);
 
         // Set flags
         self.reg_f = 0;
@@ -0,0 +1,356 @@
+use std::fmt;
+
+use super::super::GameBoy;
+use super::gb_disassembler::{disassemble, Instruction};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+    Execute,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakReason {
+    Breakpoint(usize),
+    Watchpoint {
+        id: usize,
+        address: u16,
+        kind: WatchKind,
+    },
+    Interrupt(u16),
+    SoftwareBreakpoint,
+    Step,
+    UserRequest,
+}
+
+impl fmt::Display for BreakReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakReason::Breakpoint(id) => write!(f, "hit breakpoint {id}"),
+            BreakReason::Watchpoint { id, address, kind } => {
+                let access = match kind {
+                    WatchKind::Read => "read from",
+                    WatchKind::Write => "write to",
+                    WatchKind::ReadWrite => "access to",
+                    WatchKind::Execute => "execution of",
+                };
+                write!(f, "hit watchpoint {id}, {access} ${address:04X}")
+            }
+            BreakReason::Interrupt(vector) => write!(f, "interrupt, jumped to ${vector:04X}"),
+            BreakReason::SoftwareBreakpoint => write!(f, "hit ld b, b"),
+            BreakReason::Step => write!(f, "step finished"),
+            BreakReason::UserRequest => write!(f, "break requested"),
+        }
+    }
+}
+
+pub(in super::super) struct Breakpoint {
+    pub id: usize,
+    pub address: u16,
+    pub bank: Option<usize>,
+}
+
+pub(in super::super) struct Watchpoint {
+    pub id: usize,
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(in super::super) enum StepMode {
+    Run,
+    Into,
+    // Break once the call returns, which is when we're back at the next instruction with the stack unwound
+    Over { return_address: u16, sp: u16 },
+    // Break once something has been popped off the stack frame we started in
+    Out { sp: u16 },
+    RunTo { address: u16, bank: Option<usize> },
+}
+
+pub(in super::super) struct Debugger {
+    pub enabled: bool,
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub next_id: usize,
+    pub break_on_interrupt: bool,
+    pub break_on_ld_b_b: bool,
+    pub step_mode: StepMode,
+    pub pending_break: Option<BreakReason>,
+    pub paused: Option<BreakReason>,
+    pub skip_next_check: bool,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger {
+            enabled: false,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 1,
+            break_on_interrupt: false,
+            break_on_ld_b_b: false,
+            step_mode: StepMode::Run,
+            pending_break: None,
+            paused: None,
+            skip_next_check: false,
+        }
+    }
+}
+
+impl GameBoy {
+    /// Turns the debugger hooks on or off. Breakpoints are kept either way.
+    pub fn set_debugger_enabled(&mut self, enabled: bool) {
+        self.debugger.enabled = enabled;
+        if !enabled {
+            self.debugger.paused = None;
+            self.debugger.pending_break = None;
+            self.debugger.step_mode = StepMode::Run;
+        }
+    }
+
+    /// Enables the debugger and pauses before the next instruction
+    pub fn debugger_break(&mut self) {
+        self.debugger.enabled = true;
+        self.debugger.pending_break = Some(BreakReason::UserRequest);
+    }
+
+    /// Why the machine is paused, if it is. `step` and `run_frame` do nothing until it's resumed.
+    pub fn debugger_paused(&self) -> Option<BreakReason> {
+        self.debugger.paused
+    }
+
+    /// Adds a PC breakpoint and returns its id. With a bank, it only triggers while that ROM bank is mapped at the address.
+    pub fn add_breakpoint(&mut self, address: u16, bank: Option<usize>) -> usize {
+        let id = self.debugger.next_id;
+        self.debugger.next_id += 1;
+        self.debugger
+            .breakpoints
+            .push(Breakpoint { id, address, bank });
+        id
+    }
+
+    /// Adds a watchpoint on the inclusive address range and returns its id
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, kind: WatchKind) -> usize {
+        let id = self.debugger.next_id;
+        self.debugger.next_id += 1;
+        self.debugger.watchpoints.push(Watchpoint {
+            id,
+            start: start.min(end),
+            end: start.max(end),
+            kind,
+        });
+        id
+    }
+
+    /// Removes the breakpoint or watchpoint with this id, returns false if there was none
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.debugger.breakpoints.len() + self.debugger.watchpoints.len();
+        self.debugger.breakpoints.retain(|b| b.id != id);
+        self.debugger.watchpoints.retain(|w| w.id != id);
+        count != self.debugger.breakpoints.len() + self.debugger.watchpoints.len()
+    }
+
+    pub fn set_break_on_interrupt(&mut self, enabled: bool) {
+        self.debugger.break_on_interrupt = enabled;
+    }
+
+    /// Treats `ld b, b` as a breakpoint, like BGB does. Test ROMs use it to signal they're done.
+    pub fn set_break_on_ld_b_b(&mut self, enabled: bool) {
+        self.debugger.break_on_ld_b_b = enabled;
+    }
+
+    pub fn debug_continue(&mut self) {
+        self.debugger_resume(StepMode::Run);
+    }
+
+    pub fn debug_step_into(&mut self) {
+        self.debugger_resume(StepMode::Into);
+    }
+
+    /// Like step into, but runs calls and RSTs until they return
+    pub fn debug_step_over(&mut self) {
+        let instruction = self.disassemble_at(self.pc);
+        if instruction.text.starts_with("call") || instruction.text.starts_with("rst") {
+            self.debugger_resume(StepMode::Over {
+                return_address: self.pc.wrapping_add(instruction.length),
+                sp: self.sp,
+            });
+        } else {
+            self.debugger_resume(StepMode::Into);
+        }
+    }
+
+    /// Runs until the current function returns
+    pub fn debug_step_out(&mut self) {
+        self.debugger_resume(StepMode::Out { sp: self.sp });
+    }
+
+    /// Runs until PC reaches the address, without adding a breakpoint
+    pub fn debug_run_to(&mut self, address: u16, bank: Option<usize>) {
+        self.debugger_resume(StepMode::RunTo { address, bank });
+    }
+
+    /// The ROM bank mapped at this address, or None if it's not in ROM
+    pub fn rom_bank_at(&self, address: u16) -> Option<usize> {
+        if address >= 0x8000 || self.rom.is_empty() {
+            return None;
+        }
+        Some((self.mapper.rom_offset(address) % self.rom.len()) / 0x4000)
+    }
+
+    /// Disassembles the instruction at this address as the CPU currently sees it
+    pub fn disassemble_at(&self, address: u16) -> Instruction {
+        let bytes = [
+            self.peek_byte_from_memory(address),
+            self.peek_byte_from_memory(address.wrapping_add(1)),
+            self.peek_byte_from_memory(address.wrapping_add(2)),
+        ];
+        disassemble(&bytes, address)
+    }
+
+    fn debugger_resume(&mut self, mode: StepMode) {
+        self.debugger.step_mode = mode;
+        self.debugger.paused = None;
+
+        // Don't break on the instruction we're paused on straight away
+        self.debugger.skip_next_check = true;
+    }
+
+    // Called right before an instruction is executed, returns true if the debugger wants to pause there instead
+    pub(in super::super) fn debugger_should_break(&mut self) -> bool {
+        let skip = std::mem::take(&mut self.debugger.skip_next_check);
+        if self.debugger.pending_break.is_none() && !skip {
+            self.debugger.pending_break = self.check_breakpoints();
+        }
+
+        match self.debugger.pending_break.take() {
+            Some(reason) => {
+                self.debugger.paused = Some(reason);
+                self.debugger.step_mode = StepMode::Run;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(in super::super) fn check_watchpoints(&mut self, address: u16, access: WatchKind) {
+        if self.debugger.pending_break.is_some() {
+            return;
+        }
+        for watchpoint in &self.debugger.watchpoints {
+            let kind_matches = watchpoint.kind == access
+                || (watchpoint.kind == WatchKind::ReadWrite && access != WatchKind::Execute);
+            if kind_matches && (watchpoint.start..=watchpoint.end).contains(&address) {
+                self.debugger.pending_break = Some(BreakReason::Watchpoint {
+                    id: watchpoint.id,
+                    address,
+                    kind: access,
+                });
+                return;
+            }
+        }
+    }
+
+    pub(in super::super) fn debugger_interrupt_taken(&mut self) {
+        if self.debugger.break_on_interrupt && self.debugger.pending_break.is_none() {
+            self.debugger.pending_break = Some(BreakReason::Interrupt(self.pc));
+        }
+    }
+
+    fn check_breakpoints(&mut self) -> Option<BreakReason> {
+        let bank = self.rom_bank_at(self.pc);
+        let bank_matches = |wanted: Option<usize>| wanted.is_none() || wanted == bank;
+
+        // Stepping
+        let step_done = match self.debugger.step_mode {
+            StepMode::Run => false,
+            StepMode::Into => true,
+            StepMode::Over { return_address, sp } => self.pc == return_address && self.sp >= sp,
+            StepMode::Out { sp } => self.sp > sp,
+            StepMode::RunTo { address, bank } => self.pc == address && bank_matches(bank),
+        };
+        if step_done {
+            return Some(BreakReason::Step);
+        }
+
+        // Breakpoints
+        for breakpoint in &self.debugger.breakpoints {
+            if breakpoint.address == self.pc && bank_matches(breakpoint.bank) {
+                return Some(BreakReason::Breakpoint(breakpoint.id));
+            }
+        }
+
+        if self.debugger.break_on_ld_b_b && self.peek_byte_from_memory(self.pc) == 0x40 {
+            return Some(BreakReason::SoftwareBreakpoint);
+        }
+
+        // Execute watchpoints
+        self.check_watchpoints(self.pc, WatchKind::Execute);
+        self.debugger.pending_break.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A cartridge that keeps reading $0150 in a loop
+    fn test_game_boy() -> GameBoy {
+        let mut rom = vec![0x00; 0x8000];
+        // LD A, [$0150]; JP $0100
+        rom[0x100..0x106].copy_from_slice(&[0xFA, 0x50, 0x01, 0xC3, 0x00, 0x01]);
+        let mut game_boy = GameBoy::with_power_on_seed(1);
+        assert!(game_boy.load_rom(rom));
+        game_boy.skip_boot_rom();
+        game_boy
+    }
+
+    #[test]
+    fn read_watchpoints_ignore_instruction_fetches() {
+        let mut game_boy = test_game_boy();
+        game_boy.set_debugger_enabled(true);
+        game_boy.add_watchpoint(0x0100, 0x0105, WatchKind::Read);
+        let id = game_boy.add_watchpoint(0x0150, 0x0150, WatchKind::Read);
+        for _ in 0..1000 {
+            game_boy.step();
+        }
+        assert_eq!(
+            game_boy.debugger_paused(),
+            Some(BreakReason::Watchpoint {
+                id,
+                address: 0x0150,
+                kind: WatchKind::Read
+            })
+        );
+    }
+
+    #[test]
+    fn breaking_does_not_take_a_step() {
+        let mut plain = test_game_boy();
+        let mut debugged = test_game_boy();
+        debugged.set_debugger_enabled(true);
+        let id = debugged.add_breakpoint(0x0103, None);
+        for _ in 0..1000 {
+            debugged.step();
+            if debugged.debugger_paused().is_some() {
+                break;
+            }
+            plain.step();
+        }
+        assert_eq!(
+            debugged.debugger_paused(),
+            Some(BreakReason::Breakpoint(id))
+        );
+
+        debugged.remove_breakpoint(id);
+        debugged.debug_continue();
+        for _ in 0..1000 {
+            debugged.step();
+            plain.step();
+        }
+        assert!(debugged.save_state() == plain.save_state());
+    }
+}
@@ -0,0 +1,261 @@
+use std::io::{self, BufRead, Write};
+
+use super::super::GameBoy;
+use super::gb_debugger::WatchKind;
+
+const HELP: &str = "\
+Commands:
+  c, continue               resume emulation
+  s, step                   step into the next instruction
+  n, next                   step over calls and RSTs
+  o, out                    run until the current function returns
+  u, until [bank:]addr      run to an address
+  b, break [bank:]addr      add a breakpoint, optionally only in one ROM bank
+  w, watch r|w|rw|x start[-end]
+                            add a watchpoint on an address range
+  d, delete id              remove a breakpoint or watchpoint
+  i, info                   list breakpoints and watchpoints
+  int on|off                break when an interrupt is serviced
+  ldbb on|off               break on ld b, b
+  r, regs                   print the registers
+  l, list [addr] [count]    disassemble instructions, from PC by default
+  x, mem addr [length]      print memory
+  q, detach                 turn the debugger off and resume
+  h, help                   print this";
+
+impl GameBoy {
+    /// Reads debugger commands from stdin until one of them resumes emulation
+    pub fn debugger_repl(&mut self) {
+        if let Some(reason) = self.debugger.paused {
+            println!("Paused at {}: {reason}", self.format_debug_address(self.pc));
+            self.print_disassembly(self.pc, 1);
+        }
+
+        let stdin = io::stdin();
+        let mut line = String::new();
+        loop {
+            print!("(gbdb) ");
+            let _ = io::stdout().flush();
+
+            // If stdin is closed there's no one left to talk to, so just let the game run
+            line.clear();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                self.set_debugger_enabled(false);
+                return;
+            }
+
+            if self.handle_debugger_command(line.trim()) {
+                return;
+            }
+        }
+    }
+
+    // Returns true if the command resumed emulation
+    fn handle_debugger_command(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+
+        match command {
+            "" => (),
+            "c" | "continue" => {
+                self.debug_continue();
+                return true;
+            }
+            "s" | "step" => {
+                self.debug_step_into();
+                return true;
+            }
+            "n" | "next" => {
+                self.debug_step_over();
+                return true;
+            }
+            "o" | "out" | "finish" => {
+                self.debug_step_out();
+                return true;
+            }
+            "u" | "until" => match args.first().and_then(|arg| parse_bank_address(arg)) {
+                Some((bank, address)) => {
+                    self.debug_run_to(address, bank);
+                    return true;
+                }
+                None => println!("Usage: until [bank:]addr"),
+            },
+            "b" | "break" => match args.first().and_then(|arg| parse_bank_address(arg)) {
+                Some((bank, address)) => {
+                    let id = self.add_breakpoint(address, bank);
+                    println!("Breakpoint {id} at {}", format_bank_address(bank, address));
+                }
+                None => println!("Usage: break [bank:]addr"),
+            },
+            "w" | "watch" => {
+                let kind = match args.first() {
+                    Some(&"r") => Some(WatchKind::Read),
+                    Some(&"w") => Some(WatchKind::Write),
+                    Some(&"rw") => Some(WatchKind::ReadWrite),
+                    Some(&"x") => Some(WatchKind::Execute),
+                    _ => None,
+                };
+                match (kind, args.get(1).and_then(|arg| parse_range(arg))) {
+                    (Some(kind), Some((start, end))) => {
+                        let id = self.add_watchpoint(start, end, kind);
+                        println!("Watchpoint {id} on ${start:04X}-${end:04X} ({kind:?})");
+                    }
+                    _ => println!("Usage: watch r|w|rw|x start[-end]"),
+                }
+            }
+            "d" | "delete" => match args.first().and_then(|arg| arg.parse().ok()) {
+                Some(id) => {
+                    if !self.remove_breakpoint(id) {
+                        println!("No breakpoint or watchpoint with id {id}");
+                    }
+                }
+                None => println!("Usage: delete id"),
+            },
+            "i" | "info" => {
+                for breakpoint in &self.debugger.breakpoints {
+                    println!(
+                        "{:>3}: breakpoint at {}",
+                        breakpoint.id,
+                        format_bank_address(breakpoint.bank, breakpoint.address)
+                    );
+                }
+                for watchpoint in &self.debugger.watchpoints {
+                    println!(
+                        "{:>3}: watchpoint on ${:04X}-${:04X} ({:?})",
+                        watchpoint.id, watchpoint.start, watchpoint.end, watchpoint.kind
+                    );
+                }
+                println!(
+                    "Break on interrupt: {}",
+                    if self.debugger.break_on_interrupt {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                );
+            }
+            "int" => match args.first() {
+                Some(&"on") => self.set_break_on_interrupt(true),
+                Some(&"off") => self.set_break_on_interrupt(false),
+                _ => println!("Usage: int on|off"),
+            },
+            "ldbb" => match args.first() {
+                Some(&"on") => self.set_break_on_ld_b_b(true),
+                Some(&"off") => self.set_break_on_ld_b_b(false),
+                _ => println!("Usage: ldbb on|off"),
+            },
+            "r" | "regs" => {
+                self.print_reg_state();
+                self.print_disassembly(self.pc, 1);
+            }
+            "l" | "list" => {
+                let address = match args.first() {
+                    Some(arg) => parse_address(arg),
+                    None => Some(self.pc),
+                };
+                let count = match args.get(1) {
+                    Some(arg) => arg.parse().ok(),
+                    None => Some(8),
+                };
+                match (address, count) {
+                    (Some(address), Some(count)) => self.print_disassembly(address, count),
+                    _ => println!("Usage: list [addr] [count]"),
+                }
+            }
+            "x" | "mem" => {
+                let address = args.first().and_then(|arg| parse_address(arg));
+                let length = match args.get(1) {
+                    Some(arg) => arg.parse().ok(),
+                    None => Some(64),
+                };
+                match (address, length) {
+                    (Some(address), Some(length)) => self.print_memory(address, length),
+                    _ => println!("Usage: mem addr [length]"),
+                }
+            }
+            "q" | "detach" => {
+                self.set_debugger_enabled(false);
+                return true;
+            }
+            "h" | "help" => println!("{HELP}"),
+            _ => println!("Unknown command \"{command}\", type help for a list"),
+        }
+        false
+    }
+
+    fn print_memory(&self, start: u16, length: usize) {
+        for row_start in (0..length).step_by(16) {
+            let address = start.wrapping_add(row_start as u16);
+            let bytes: Vec<String> = (0..16.min(length - row_start))
+                .map(|i| {
+                    format!(
+                        "{:02X}",
+                        self.peek_byte_from_memory(address.wrapping_add(i as u16))
+                    )
+                })
+                .collect();
+            println!("${address:04X}: {}", bytes.join(" "));
+        }
+    }
+
+    fn print_disassembly(&self, start: u16, count: usize) {
+        let mut address = start;
+        for _ in 0..count {
+            let instruction = self.disassemble_at(address);
+            let bytes: Vec<String> = (0..instruction.length)
+                .map(|i| {
+                    format!(
+                        "{:02X}",
+                        self.peek_byte_from_memory(address.wrapping_add(i))
+                    )
+                })
+                .collect();
+            println!(
+                "{:<8} {:<9} {}",
+                self.format_debug_address(address),
+                bytes.join(" "),
+                instruction.text
+            );
+            address = address.wrapping_add(instruction.length);
+        }
+    }
+
+    fn format_debug_address(&self, address: u16) -> String {
+        format_bank_address(self.rom_bank_at(address), address)
+    }
+}
+
+fn format_bank_address(bank: Option<usize>, address: u16) -> String {
+    match bank {
+        Some(bank) => format!("{bank:02X}:{address:04X}"),
+        None => format!("${address:04X}"),
+    }
+}
+
+// Addresses are hex, with an optional $ or 0x in front
+fn parse_address(text: &str) -> Option<u16> {
+    let text = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(text, 16).ok()
+}
+
+// Either "addr" or "bank:addr", both in hex
+fn parse_bank_address(text: &str) -> Option<(Option<usize>, u16)> {
+    match text.split_once(':') {
+        Some((bank, address)) => Some((
+            Some(usize::from_str_radix(bank, 16).ok()?),
+            parse_address(address)?,
+        )),
+        None => Some((None, parse_address(text)?)),
+    }
+}
+
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    match text.split_once('-') {
+        Some((start, end)) => Some((parse_address(start)?, parse_address(end)?)),
+        None => {
+            let address = parse_address(text)?;
+            Some((address, address))
+        }
+    }
+}
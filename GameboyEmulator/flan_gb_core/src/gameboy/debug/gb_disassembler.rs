@@ -0,0 +1,260 @@
+// Operand names, in the same order the opcodes encode them
+const R8: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const R16: [&str; 4] = ["bc", "de", "hl", "sp"];
+const R16_STACK: [&str; 4] = ["bc", "de", "hl", "af"];
+const R16_POINTER: [&str; 4] = ["[bc]", "[de]", "[hl+]", "[hl-]"];
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = [
+    "add a,", "adc a,", "sub", "sbc a,", "and", "xor", "or", "cp",
+];
+const ROTATIONS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+/// One decoded instruction, in RGBDS syntax
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    pub text: String,
+    pub length: u16,
+}
+
+/// Decodes the instruction at the start of `bytes`, which should hold up to 3 bytes read from `address`.
+/// Opcodes the CPU doesn't have come out as `db`, and so do the bytes of an instruction that gets cut off
+/// before its operands, like at the end of a bank.
+pub fn disassemble(bytes: &[u8], address: u16) -> Instruction {
+    let instruction = decode(bytes, address);
+    if bytes.is_empty() || bytes.len() >= instruction.length as usize {
+        return instruction;
+    }
+    let text: Vec<String> = bytes.iter().map(|byte| format!("${byte:02X}")).collect();
+    Instruction {
+        text: format!("db {}", text.join(", ")),
+        length: bytes.len() as u16,
+    }
+}
+
+fn decode(bytes: &[u8], address: u16) -> Instruction {
+    let byte = |index: usize| bytes.get(index).copied().unwrap_or(0);
+    let opcode = byte(0);
+    let d8 = byte(1);
+    let d16 = u16::from_le_bytes([byte(1), byte(2)]);
+    let e8 = d8 as i8;
+    let jr_target = address.wrapping_add(2).wrapping_add(d8 as i8 as u16);
+
+    // Most instructions keep their operand in bits 3-5 or 4-5
+    let r8_high = R8[((opcode >> 3) & 0x07) as usize];
+    let r8_low = R8[(opcode & 0x07) as usize];
+    let r16 = ((opcode >> 4) & 0x03) as usize;
+    let condition = CONDITIONS[((opcode >> 3) & 0x03) as usize];
+
+    let (text, length) = match opcode {
+        0xCB => return disassemble_prefixed(byte(1)),
+
+        // Misc
+        0x00 => ("nop".to_string(), 1),
+        0x10 => ("stop".to_string(), 2),
+        0x76 => ("halt".to_string(), 1),
+        0xF3 => ("di".to_string(), 1),
+        0xFB => ("ei".to_string(), 1),
+        0x07 => ("rlca".to_string(), 1),
+        0x0F => ("rrca".to_string(), 1),
+        0x17 => ("rla".to_string(), 1),
+        0x1F => ("rra".to_string(), 1),
+        0x27 => ("daa".to_string(), 1),
+        0x2F => ("cpl".to_string(), 1),
+        0x37 => ("scf".to_string(), 1),
+        0x3F => ("ccf".to_string(), 1),
+
+        // LD r8, r8
+        0x40..=0x7F => (format!("ld {r8_high}, {r8_low}"), 1),
+
+        // LD r8, d8
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => {
+            (format!("ld {r8_high}, ${d8:02X}"), 2)
+        }
+
+        // LD [r16], A and LD A, [r16]
+        0x02 | 0x12 | 0x22 | 0x32 => (format!("ld {}, a", R16_POINTER[r16]), 1),
+        0x0A | 0x1A | 0x2A | 0x3A => (format!("ld a, {}", R16_POINTER[r16]), 1),
+
+        // LDH and LD with absolute addresses
+        0xE0 => (format!("ldh [$FF{d8:02X}], a"), 2),
+        0xF0 => (format!("ldh a, [$FF{d8:02X}]"), 2),
+        0xE2 => ("ldh [c], a".to_string(), 1),
+        0xF2 => ("ldh a, [c]".to_string(), 1),
+        0xEA => (format!("ld [${d16:04X}], a"), 3),
+        0xFA => (format!("ld a, [${d16:04X}]"), 3),
+
+        // 16-bit loads
+        0x01 | 0x11 | 0x21 | 0x31 => (format!("ld {}, ${d16:04X}", R16[r16]), 3),
+        0x08 => (format!("ld [${d16:04X}], sp"), 3),
+        0xF8 => (format!("ld hl, sp {}", signed_offset(d8)), 2),
+        0xF9 => ("ld sp, hl".to_string(), 1),
+        0xC1 | 0xD1 | 0xE1 | 0xF1 => (format!("pop {}", R16_STACK[r16]), 1),
+        0xC5 | 0xD5 | 0xE5 | 0xF5 => (format!("push {}", R16_STACK[r16]), 1),
+
+        // 8-bit arithmetic
+        0x80..=0xBF => (
+            format!("{} {r8_low}", ALU[((opcode >> 3) & 0x07) as usize]),
+            1,
+        ),
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => (
+            format!("{} ${d8:02X}", ALU[((opcode >> 3) & 0x07) as usize]),
+            2,
+        ),
+        0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C => (format!("inc {r8_high}"), 1),
+        0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D => (format!("dec {r8_high}"), 1),
+
+        // 16-bit arithmetic
+        0x03 | 0x13 | 0x23 | 0x33 => (format!("inc {}", R16[r16]), 1),
+        0x0B | 0x1B | 0x2B | 0x3B => (format!("dec {}", R16[r16]), 1),
+        0x09 | 0x19 | 0x29 | 0x39 => (format!("add hl, {}", R16[r16]), 1),
+        0xE8 => (format!("add sp, {e8}"), 2),
+
+        // Jumps
+        0x18 => (format!("jr ${jr_target:04X}"), 2),
+        0x20 | 0x28 | 0x30 | 0x38 => (format!("jr {condition}, ${jr_target:04X}"), 2),
+        0xC3 => (format!("jp ${d16:04X}"), 3),
+        0xC2 | 0xCA | 0xD2 | 0xDA => (format!("jp {condition}, ${d16:04X}"), 3),
+        0xE9 => ("jp hl".to_string(), 1),
+
+        // Calls and returns
+        0xCD => (format!("call ${d16:04X}"), 3),
+        0xC4 | 0xCC | 0xD4 | 0xDC => (format!("call {condition}, ${d16:04X}"), 3),
+        0xC9 => ("ret".to_string(), 1),
+        0xD9 => ("reti".to_string(), 1),
+        0xC0 | 0xC8 | 0xD0 | 0xD8 => (format!("ret {condition}"), 1),
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+            (format!("rst ${:02X}", opcode & 0x38), 1)
+        }
+
+        // Holes in the opcode table
+        0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+            (format!("db ${opcode:02X}"), 1)
+        }
+    };
+
+    Instruction { text, length }
+}
+
+fn disassemble_prefixed(opcode: u8) -> Instruction {
+    let register = R8[(opcode & 0x07) as usize];
+    let bit = (opcode >> 3) & 0x07;
+    let text = match opcode {
+        0x00..=0x3F => format!("{} {register}", ROTATIONS[bit as usize]),
+        0x40..=0x7F => format!("bit {bit}, {register}"),
+        0x80..=0xBF => format!("res {bit}, {register}"),
+        0xC0..=0xFF => format!("set {bit}, {register}"),
+    };
+    Instruction { text, length: 2 }
+}
+
+// RGBDS wants "sp + 5" or "sp - 5"
+fn signed_offset(value: u8) -> String {
+    let value = value as i8;
+    if value < 0 {
+        format!("- {}", value.unsigned_abs())
+    } else {
+        format!("+ {value}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8], address: u16) -> String {
+        disassemble(bytes, address).text
+    }
+
+    #[test]
+    fn loads() {
+        assert_eq!(text(&[0x41], 0), "ld b, c");
+        assert_eq!(text(&[0x7E], 0), "ld a, [hl]");
+        assert_eq!(text(&[0x36, 0x12], 0), "ld [hl], $12");
+        assert_eq!(text(&[0x2A], 0), "ld a, [hl+]");
+        assert_eq!(text(&[0x32], 0), "ld [hl-], a");
+        assert_eq!(text(&[0xE0, 0x40], 0), "ldh [$FF40], a");
+        assert_eq!(text(&[0xFA, 0x34, 0x12], 0), "ld a, [$1234]");
+        assert_eq!(text(&[0x31, 0xFE, 0xFF], 0), "ld sp, $FFFE");
+        assert_eq!(text(&[0x08, 0x00, 0xC0], 0), "ld [$C000], sp");
+        assert_eq!(text(&[0xF8, 0xFB], 0), "ld hl, sp - 5");
+        assert_eq!(text(&[0xF8, 0x05], 0), "ld hl, sp + 5");
+        assert_eq!(text(&[0xF1], 0), "pop af");
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(text(&[0x80], 0), "add a, b");
+        assert_eq!(text(&[0x96], 0), "sub [hl]");
+        assert_eq!(text(&[0xFE, 0x90], 0), "cp $90");
+        assert_eq!(text(&[0x3C], 0), "inc a");
+        assert_eq!(text(&[0x2B], 0), "dec hl");
+        assert_eq!(text(&[0x39], 0), "add hl, sp");
+        assert_eq!(text(&[0xE8, 0xFE], 0), "add sp, -2");
+    }
+
+    #[test]
+    fn jumps_are_relative_to_the_next_instruction() {
+        assert_eq!(text(&[0x18, 0xFE], 0x0150), "jr $0150");
+        assert_eq!(text(&[0x20, 0x05], 0x0150), "jr nz, $0157");
+        assert_eq!(text(&[0x38, 0x80], 0x0010), "jr c, $FF92");
+        assert_eq!(text(&[0xC3, 0x50, 0x01], 0), "jp $0150");
+        assert_eq!(text(&[0xDA, 0x00, 0x40], 0), "jp c, $4000");
+        assert_eq!(text(&[0xCC, 0x00, 0x20], 0), "call z, $2000");
+        assert_eq!(text(&[0xD0], 0), "ret nc");
+        assert_eq!(text(&[0xEF], 0), "rst $28");
+    }
+
+    #[test]
+    fn prefixed() {
+        assert_eq!(text(&[0xCB, 0x37], 0), "swap a");
+        assert_eq!(text(&[0xCB, 0x06], 0), "rlc [hl]");
+        assert_eq!(text(&[0xCB, 0x7C], 0), "bit 7, h");
+        assert_eq!(text(&[0xCB, 0x87], 0), "res 0, a");
+        assert_eq!(text(&[0xCB, 0xDE], 0), "set 3, [hl]");
+    }
+
+    #[test]
+    fn misc() {
+        assert_eq!(text(&[0x76], 0), "halt");
+        assert_eq!(text(&[0x10, 0x00], 0), "stop");
+        assert_eq!(text(&[0xD3], 0), "db $D3");
+    }
+
+    #[test]
+    fn cut_off_operands_are_data() {
+        assert_eq!(
+            disassemble(&[0xC3, 0x50], 0x3FFE),
+            Instruction {
+                text: "db $C3, $50".to_string(),
+                length: 2
+            }
+        );
+        assert_eq!(text(&[0xCB], 0x3FFF), "db $CB");
+        assert_eq!(text(&[0x3E], 0x3FFF), "db $3E");
+        assert_eq!(text(&[0x00], 0x3FFF), "nop");
+    }
+
+    #[test]
+    fn lengths() {
+        let mut lengths = [1u16; 256];
+        for opcode in [
+            0x06, 0x0E, 0x10, 0x16, 0x18, 0x1E, 0x20, 0x26, 0x28, 0x2E, 0x30, 0x36, 0x38, 0x3E,
+            0xC6, 0xCB, 0xCE, 0xD6, 0xDE, 0xE0, 0xE6, 0xE8, 0xEE, 0xF0, 0xF6, 0xF8, 0xFE,
+        ] {
+            lengths[opcode] = 2;
+        }
+        for opcode in [
+            0x01, 0x08, 0x11, 0x21, 0x31, 0xC2, 0xC3, 0xC4, 0xCA, 0xCC, 0xCD, 0xD2, 0xD4, 0xDA,
+            0xDC, 0xEA, 0xFA,
+        ] {
+            lengths[opcode] = 3;
+        }
+        for (opcode, length) in lengths.into_iter().enumerate() {
+            assert_eq!(
+                disassemble(&[opcode as u8, 0x00, 0x00], 0).length,
+                length,
+                "opcode ${opcode:02X}"
+            );
+        }
+    }
+}
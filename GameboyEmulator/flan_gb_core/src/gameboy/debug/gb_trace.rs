@@ -0,0 +1,75 @@
+use std::io::{BufWriter, Write};
+
+use super::super::GameBoy;
+
+impl GameBoy {
+    /// Starts writing a line in the Gameboy Doctor format before every instruction.
+    /// Output is buffered, and flushed when tracing stops or the Game Boy is dropped.
+    /// Like the emulators the reference logs come from, LY (0xFF44) reads as 0x90 while a trace log is active.
+    pub fn start_trace_log(&mut self, writer: Box<dyn Write>) {
+        self.trace_log = Some(BufWriter::new(writer));
+    }
+
+    pub fn stop_trace_log(&mut self) {
+        if let Some(mut log) = self.trace_log.take() {
+            let _ = log.flush();
+        }
+    }
+
+    pub(in super::super) fn write_trace_line(&mut self) {
+        // Reference logs start after the boot ROM, so leave it out
+        if self.rom_chip_enabled {
+            return;
+        }
+
+        let pcmem = [
+            self.peek_byte_from_memory(self.pc),
+            self.peek_byte_from_memory(self.pc.wrapping_add(1)),
+            self.peek_byte_from_memory(self.pc.wrapping_add(2)),
+            self.peek_byte_from_memory(self.pc.wrapping_add(3)),
+        ];
+        let Some(log) = &mut self.trace_log else {
+            return;
+        };
+        let result = writeln!(
+            log,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.reg_a,
+            self.reg_f,
+            self.reg_b,
+            self.reg_c,
+            self.reg_d,
+            self.reg_e,
+            self.reg_h,
+            self.reg_l,
+            self.sp,
+            self.pc,
+            pcmem[0],
+            pcmem[1],
+            pcmem[2],
+            pcmem[3],
+        );
+
+        // Don't keep trying if the disk is full or the pipe went away
+        if let Err(e) = result {
+            println!("Unable to write trace log, stopping it: {e}");
+            self.trace_log = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ly_is_stubbed_while_tracing() {
+        let mut game_boy = GameBoy::with_power_on_seed(1);
+        game_boy.io[0x44] = 0x12;
+        assert_eq!(game_boy.peek_byte_from_memory(0xFF44), 0x12);
+        game_boy.start_trace_log(Box::new(std::io::sink()));
+        assert_eq!(game_boy.peek_byte_from_memory(0xFF44), 0x90);
+        game_boy.stop_trace_log();
+        assert_eq!(game_boy.peek_byte_from_memory(0xFF44), 0x12);
+    }
+}
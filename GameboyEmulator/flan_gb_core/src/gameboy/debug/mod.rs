@@ -0,0 +1,4 @@
+pub mod gb_debugger;
+pub mod gb_debugger_repl;
+pub mod gb_disassembler;
+pub mod gb_trace;
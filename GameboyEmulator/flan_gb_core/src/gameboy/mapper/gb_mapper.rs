@@ -0,0 +1,80 @@
+use super::gb_mbc1::Mbc1;
+use super::gb_mbc2::Mbc2;
+use super::gb_mbc3::Mbc3;
+use super::gb_mbc5::Mbc5;
+use super::gb_rom_only::RomOnly;
+use crate::gameboy::state::gb_state_io::{SaveStateError, StateReader, StateWriter};
+
+// The memory bank controller on the cartridge. The CPU talks to it through the ROM and ERAM
+// address ranges, and it decides which part of the ROM and ERAM chips ends up where.
+pub trait Mapper {
+    // Translate an address in 0x0000..=0x7FFF into an offset into the ROM
+    fn rom_offset(&self, address: u16) -> usize;
+
+    // Handle a write to 0x0000..=0x7FFF, which goes to the mapper's registers
+    fn write_register(&mut self, address: u16, value: u8);
+
+    // Translate an address in 0xA000..=0xBFFF into an offset into the RAM, or None while no RAM is mapped there
+    fn ram_offset(&self, ram: &[u8], address: u16) -> Option<usize>;
+
+    // Handle a read from 0xA000..=0xBFFF, open bus reads as 0xFF
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        self.ram_offset(ram, address)
+            .map_or(0xFF, |offset| ram[offset])
+    }
+
+    // Handle a write to 0xA000..=0xBFFF
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(ram, address) {
+            ram[offset] = value;
+        }
+    }
+
+    // Should be called every 2 dots, also in double speed mode, for mappers that keep time
+    fn run_cycle(&mut self) {}
+
+    // Whether the mapper has a real-time clock, which needs to be saved even if there is no RAM
+    fn has_rtc(&self) -> bool {
+        false
+    }
+
+    // Extra state that gets appended to the save file after the RAM contents
+    fn save_footer(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    // Restore the extra state that was appended to the save file
+    fn load_footer(&mut self, _footer: &[u8]) {}
+
+    // Write the mapper registers into a save state
+    fn write_state(&self, state: &mut StateWriter);
+
+    // Read the mapper registers back from a save state
+    fn read_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+// Pick a mapper based on the cartridge type byte in the ROM header (0x147)
+pub fn create_mapper(cartridge_type: u8) -> Box<dyn Mapper> {
+    match cartridge_type {
+        0x00 | 0x08 | 0x09 => Box::new(RomOnly::new()),
+        0x01..=0x03 => Box::new(Mbc1::new()),
+        0x05 | 0x06 => Box::new(Mbc2::new()),
+        0x0F | 0x10 => Box::new(Mbc3::new(true)),
+        0x11..=0x13 => Box::new(Mbc3::new(false)),
+        0x19..=0x1E => Box::new(Mbc5::new()),
+        _ => {
+            println!("Cartridge type ${cartridge_type:02X} is not supported, falling back to MBC5");
+            Box::new(Mbc5::new())
+        }
+    }
+}
+
+// Get the offset into ERAM for an address in 0xA000..=0xBFFF in a given bank, wrapping around the RAM size
+pub(super) fn banked_ram_offset(ram: &[u8], bank: usize, address: u16) -> usize {
+    (bank * 0x2000 + (address as usize & 0x1FFF)) % ram.len()
+}
+
+// Get the offset into ROM for an address in 0x0000..=0x3FFF or 0x4000..=0x7FFF in a given bank
+pub(super) fn banked_rom_offset(bank: usize, address: u16) -> usize {
+    bank * 0x4000 + (address as usize & 0x3FFF)
+}
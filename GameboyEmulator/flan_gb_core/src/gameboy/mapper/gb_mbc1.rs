@@ -0,0 +1,136 @@
+use crate::gameboy::state::gb_state_io::{SaveStateError, StateReader, StateWriter};
+
+use super::gb_mapper::{banked_ram_offset, banked_rom_offset, Mapper};
+
+pub struct Mbc1 {
+    ram_enabled: bool,
+    bank1: u8,        // 5 bit register at 0x2000..=0x3FFF, lower bits of the ROM bank
+    bank2: u8,        // 2 bit register at 0x4000..=0x5FFF, RAM bank or upper bits of the ROM bank
+    banking_mode: u8, // 1 bit register at 0x6000..=0x7FFF
+}
+
+impl Mbc1 {
+    pub fn new() -> Mbc1 {
+        Mbc1 {
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            banking_mode: 0,
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        // In mode 0, only the first RAM bank is accessible
+        match self.banking_mode {
+            0 => 0,
+            _ => self.bank2 as usize,
+        }
+    }
+}
+
+impl Mapper for Mbc1 {
+    fn rom_offset(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            // In mode 1, BANK2 also applies to the 0x0000..=0x3FFF area, this is how large ROMs can reach banks $20, $40 and $60
+            match self.banking_mode {
+                0 => banked_rom_offset(0, address),
+                _ => banked_rom_offset((self.bank2 as usize) << 5, address),
+            }
+        } else {
+            banked_rom_offset(((self.bank2 as usize) << 5) | self.bank1 as usize, address)
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // Bank 0 can not be selected here, it turns into bank 1. This check only looks at the 5 bits in this register
+                self.bank1 = value & 0x1F;
+                if self.bank1 == 0 {
+                    self.bank1 = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            0x6000..=0x7FFF => self.banking_mode = value & 0x01,
+            _ => (),
+        }
+    }
+
+    fn ram_offset(&self, ram: &[u8], address: u16) -> Option<usize> {
+        match self.ram_enabled && !ram.is_empty() {
+            true => Some(banked_ram_offset(ram, self.ram_bank(), address)),
+            false => None,
+        }
+    }
+
+    fn write_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u8(self.bank1);
+        state.u8(self.bank2);
+        state.u8(self.banking_mode);
+    }
+
+    fn read_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enabled = state.bool()?;
+        self.bank1 = state.u8()?;
+        self.bank2 = state.u8()?;
+        self.banking_mode = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_bank_switching() {
+        let mut mbc = Mbc1::new();
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+
+        mbc.write_register(0x2000, 0x05);
+        assert_eq!(mbc.rom_offset(0x4123), 0x05 * 0x4000 + 0x123);
+
+        // Only the low 5 bits are checked for 0, so $20 selects bank 1 as well
+        mbc.write_register(0x2000, 0x00);
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+        mbc.write_register(0x3FFF, 0x20);
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+
+        // BANK2 supplies bits 5 and 6 of the bank number
+        mbc.write_register(0x4000, 0x02);
+        assert_eq!(mbc.rom_offset(0x4000), 0x41 * 0x4000);
+        assert_eq!(mbc.rom_offset(0x0000), 0);
+
+        // And in mode 1 it applies to the first area too
+        mbc.write_register(0x6000, 0x01);
+        assert_eq!(mbc.rom_offset(0x0010), 0x40 * 0x4000 + 0x10);
+    }
+
+    #[test]
+    fn ram_bank_switching() {
+        let mut mbc = Mbc1::new();
+        let mut ram = vec![0; 0x8000];
+
+        // RAM starts out disabled
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+        assert_eq!(ram[0], 0);
+
+        mbc.write_register(0x0000, 0x0A);
+        mbc.write_register(0x4000, 0x03);
+
+        // In mode 0 BANK2 doesn't reach the RAM
+        mbc.write_ram(&mut ram, 0xA001, 0x34);
+        assert_eq!(ram[0x0001], 0x34);
+
+        mbc.write_register(0x6000, 0x01);
+        mbc.write_ram(&mut ram, 0xA001, 0x56);
+        assert_eq!(ram[0x6001], 0x56);
+        assert_eq!(mbc.read_ram(&ram, 0xA001), 0x56);
+
+        mbc.write_register(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA001), 0xFF);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::gameboy::state::gb_state_io::{SaveStateError, StateReader, StateWriter};
+
+use super::gb_mapper::{banked_rom_offset, Mapper};
+
+// MBC2 has 512 half-bytes of RAM built into the mapper chip itself
+pub const MBC2_RAM_SIZE: usize = 512;
+
+pub struct Mbc2 {
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new() -> Mbc2 {
+        Mbc2 {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Mapper for Mbc2 {
+    fn rom_offset(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            banked_rom_offset(0, address)
+        } else {
+            banked_rom_offset(self.rom_bank as usize, address)
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        // Only 0x0000..=0x3FFF is used, and bit 8 of the address decides which register gets written
+        if address >= 0x4000 {
+            return;
+        }
+        if address & 0x0100 == 0 {
+            self.ram_enabled = value & 0x0F == 0x0A;
+        } else {
+            self.rom_bank = value & 0x0F;
+            if self.rom_bank == 0 {
+                self.rom_bank = 1;
+            }
+        }
+    }
+
+    fn ram_offset(&self, ram: &[u8], address: u16) -> Option<usize> {
+        // The RAM is mirrored across the whole area
+        match self.ram_enabled && !ram.is_empty() {
+            true => Some((address as usize & 0x01FF) % ram.len()),
+            false => None,
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        // The upper 4 bits are not connected
+        self.ram_offset(ram, address)
+            .map_or(0xFF, |offset| ram[offset] | 0xF0)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(ram, address) {
+            ram[offset] = value & 0x0F;
+        }
+    }
+
+    fn write_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u8(self.rom_bank);
+    }
+
+    fn read_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_bank_switching() {
+        let mut mbc = Mbc2::new();
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+
+        // Bit 8 of the address picks the ROM bank register
+        mbc.write_register(0x2100, 0x0F);
+        assert_eq!(mbc.rom_offset(0x7FFF), 0x0F * 0x4000 + 0x3FFF);
+        mbc.write_register(0x0100, 0x13);
+        assert_eq!(mbc.rom_offset(0x4000), 0x03 * 0x4000);
+        mbc.write_register(0x0100, 0x00);
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+
+        // Without it, the write goes to the RAM enable register and the bank stays
+        mbc.write_register(0x2000, 0x05);
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+        mbc.write_register(0x4100, 0x05);
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+    }
+
+    #[test]
+    fn ram_is_4_bit_and_mirrored() {
+        let mut mbc = Mbc2::new();
+        let mut ram = vec![0; MBC2_RAM_SIZE];
+
+        mbc.write_register(0x0100, 0x0A);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc.write_register(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA005, 0xAB);
+        assert_eq!(ram[5], 0x0B);
+        assert_eq!(mbc.read_ram(&ram, 0xA005), 0xFB);
+        assert_eq!(mbc.read_ram(&ram, 0xA205), 0xFB);
+        assert_eq!(mbc.read_ram(&ram, 0xBE05), 0xFB);
+    }
+}
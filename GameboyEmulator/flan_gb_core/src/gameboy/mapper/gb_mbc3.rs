@@ -0,0 +1,351 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::gameboy::state::gb_state_io::{SaveStateError, StateReader, StateWriter};
+
+use super::gb_mapper::{banked_ram_offset, banked_rom_offset, Mapper};
+
+// The RTC counts seconds off of run_cycle calls, which come once every 2 dots of the 4 MiHz clock
+const CYCLES_PER_SECOND: u32 = 1 << 21;
+
+// Size of the RTC footer at the end of a save file. Some emulators write a 32-bit timestamp instead of a 64-bit one
+const RTC_FOOTER_SIZE: usize = 48;
+const RTC_FOOTER_SIZE_SHORT: usize = 44;
+
+// Day high register bits
+const DAY_HIGH_DAY_BIT_8: u8 = 1 << 0;
+const DAY_HIGH_HALT: u8 = 1 << 6;
+const DAY_HIGH_DAY_CARRY: u8 = 1 << 7;
+
+#[derive(Default, Clone, Copy)]
+struct RtcRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day_low: u8,
+    day_high: u8,
+}
+
+impl RtcRegisters {
+    fn days(&self) -> u16 {
+        self.day_low as u16 | ((self.day_high & DAY_HIGH_DAY_BIT_8) as u16) << 8
+    }
+
+    fn set_days(&mut self, days: u16) {
+        self.day_low = (days & 0xFF) as u8;
+        self.day_high = (self.day_high & !DAY_HIGH_DAY_BIT_8) | ((days >> 8) as u8 & 0x01);
+    }
+
+    fn is_in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    fn tick_second(&mut self) {
+        // Out of range values count up until the register overflows, and then wrap to 0 without carrying
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        let days = self.days() + 1;
+        if days == 512 {
+            self.day_high |= DAY_HIGH_DAY_CARRY;
+        }
+        self.set_days(days % 512);
+    }
+
+    fn advance(&mut self, mut seconds: u64) {
+        if self.day_high & DAY_HIGH_HALT != 0 {
+            return;
+        }
+
+        // Get the registers back into a sane state the slow way first
+        while seconds > 0 && !self.is_in_range() {
+            self.tick_second();
+            seconds -= 1;
+        }
+
+        // Then add the rest all at once
+        let total = self.seconds as u64
+            + 60 * (self.minutes as u64 + 60 * (self.hours as u64 + 24 * self.days() as u64))
+            + seconds;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = total / 86400;
+        if days >= 512 {
+            self.day_high |= DAY_HIGH_DAY_CARRY;
+        }
+        self.set_days((days % 512) as u16);
+    }
+
+    fn write_to_footer(&self, footer: &mut Vec<u8>) {
+        for value in [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.day_low,
+            self.day_high,
+        ] {
+            footer.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+    }
+
+    fn read_from_footer(footer: &[u8]) -> RtcRegisters {
+        let value = |index: usize| footer[index * 4];
+        RtcRegisters {
+            seconds: value(0) & 0x3F,
+            minutes: value(1) & 0x3F,
+            hours: value(2) & 0x1F,
+            day_low: value(3),
+            day_high: value(4) & (DAY_HIGH_DAY_BIT_8 | DAY_HIGH_HALT | DAY_HIGH_DAY_CARRY),
+        }
+    }
+}
+
+pub struct Mbc3 {
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8, // 0x00..=0x03 selects a RAM bank, 0x08..=0x0C selects an RTC register
+    has_rtc: bool,
+    rtc: RtcRegisters,
+    rtc_latched: RtcRegisters,
+    rtc_latch_armed: bool,
+    rtc_cycle_counter: u32,
+}
+
+impl Mbc3 {
+    pub fn new(has_rtc: bool) -> Mbc3 {
+        Mbc3 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rtc,
+            rtc: RtcRegisters::default(),
+            rtc_latched: RtcRegisters::default(),
+            rtc_latch_armed: false,
+            rtc_cycle_counter: 0,
+        }
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn rom_offset(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            banked_rom_offset(0, address)
+        } else {
+            banked_rom_offset(self.rom_bank as usize, address)
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value & 0x7F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            0x6000..=0x7FFF => {
+                // Writing 0x00 and then 0x01 copies the clock into the registers the CPU can read
+                if self.rtc_latch_armed && value == 0x01 {
+                    self.rtc_latched = self.rtc;
+                }
+                self.rtc_latch_armed = value == 0x00;
+            }
+            _ => (),
+        }
+    }
+
+    fn ram_offset(&self, ram: &[u8], address: u16) -> Option<usize> {
+        match self.ram_enabled && self.ram_bank <= 0x03 && !ram.is_empty() {
+            true => Some(banked_ram_offset(ram, self.ram_bank as usize, address)),
+            false => None,
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if let Some(offset) = self.ram_offset(ram, address) {
+            return ram[offset];
+        }
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match self.ram_bank {
+            0x08 if self.has_rtc => self.rtc_latched.seconds,
+            0x09 if self.has_rtc => self.rtc_latched.minutes,
+            0x0A if self.has_rtc => self.rtc_latched.hours,
+            0x0B if self.has_rtc => self.rtc_latched.day_low,
+            0x0C if self.has_rtc => self.rtc_latched.day_high | 0b0011_1110,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(ram, address) {
+            ram[offset] = value;
+            return;
+        }
+        if !self.ram_enabled {
+            return;
+        }
+        match self.ram_bank {
+            0x08 if self.has_rtc => {
+                // Writing the seconds also resets the part of the clock that counts up to the next second
+                self.rtc.seconds = value & 0x3F;
+                self.rtc_cycle_counter = 0;
+            }
+            0x09 if self.has_rtc => self.rtc.minutes = value & 0x3F,
+            0x0A if self.has_rtc => self.rtc.hours = value & 0x1F,
+            0x0B if self.has_rtc => self.rtc.day_low = value,
+            0x0C if self.has_rtc => {
+                self.rtc.day_high =
+                    value & (DAY_HIGH_DAY_BIT_8 | DAY_HIGH_HALT | DAY_HIGH_DAY_CARRY)
+            }
+            _ => (),
+        }
+    }
+
+    fn run_cycle(&mut self) {
+        if !self.has_rtc || self.rtc.day_high & DAY_HIGH_HALT != 0 {
+            return;
+        }
+        self.rtc_cycle_counter += 1;
+        if self.rtc_cycle_counter == CYCLES_PER_SECOND {
+            self.rtc_cycle_counter = 0;
+            self.rtc.tick_second();
+        }
+    }
+
+    fn has_rtc(&self) -> bool {
+        self.has_rtc
+    }
+
+    fn save_footer(&self) -> Vec<u8> {
+        if !self.has_rtc {
+            return Vec::new();
+        }
+
+        // Current registers, latched registers, and a 64-bit UNIX timestamp, all little endian
+        let mut footer = Vec::with_capacity(RTC_FOOTER_SIZE);
+        self.rtc.write_to_footer(&mut footer);
+        self.rtc_latched.write_to_footer(&mut footer);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        footer.extend_from_slice(&timestamp.to_le_bytes());
+        footer
+    }
+
+    fn load_footer(&mut self, footer: &[u8]) {
+        if !self.has_rtc {
+            return;
+        }
+
+        let timestamp = match footer.len() {
+            RTC_FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            RTC_FOOTER_SIZE_SHORT => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            _ => {
+                println!(
+                    "Save file has an RTC footer of unknown size {}, ignoring it",
+                    footer.len()
+                );
+                return;
+            }
+        };
+        self.rtc = RtcRegisters::read_from_footer(&footer[0..20]);
+        self.rtc_latched = RtcRegisters::read_from_footer(&footer[20..40]);
+
+        // Catch up on the time that passed while the emulator wasn't running
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        self.rtc.advance(now.saturating_sub(timestamp));
+    }
+
+    fn write_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u8(self.rom_bank);
+        state.u8(self.ram_bank);
+        for rtc in [&self.rtc, &self.rtc_latched] {
+            state.u8(rtc.seconds);
+            state.u8(rtc.minutes);
+            state.u8(rtc.hours);
+            state.u8(rtc.day_low);
+            state.u8(rtc.day_high);
+        }
+        state.bool(self.rtc_latch_armed);
+        state.u32(self.rtc_cycle_counter);
+    }
+
+    fn read_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = state.u8()?;
+        self.ram_bank = state.u8()?;
+        for rtc in [&mut self.rtc, &mut self.rtc_latched] {
+            rtc.seconds = state.u8()?;
+            rtc.minutes = state.u8()?;
+            rtc.hours = state.u8()?;
+            rtc.day_low = state.u8()?;
+            rtc.day_high = state.u8()?;
+        }
+        self.rtc_latch_armed = state.bool()?;
+        self.rtc_cycle_counter = state.u32()? % CYCLES_PER_SECOND;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_bank_switching() {
+        let mut mbc = Mbc3::new(false);
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+
+        mbc.write_register(0x2000, 0x7F);
+        assert_eq!(mbc.rom_offset(0x4000), 0x7F * 0x4000);
+        assert_eq!(mbc.rom_offset(0x3FFF), 0x3FFF);
+        mbc.write_register(0x3FFF, 0x80);
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+    }
+
+    #[test]
+    fn ram_and_rtc_bank_switching() {
+        let mut mbc = Mbc3::new(true);
+        let mut ram = vec![0; 0x8000];
+
+        mbc.write_register(0x0000, 0x0A);
+        mbc.write_register(0x4000, 0x02);
+        mbc.write_ram(&mut ram, 0xA010, 0x42);
+        assert_eq!(ram[0x4010], 0x42);
+        assert_eq!(mbc.read_ram(&ram, 0xA010), 0x42);
+
+        // Setting the seconds doesn't show until the clock is latched
+        mbc.write_register(0x4000, 0x08);
+        mbc.write_ram(&mut ram, 0xA000, 30);
+        assert_eq!(ram[0x4010], 0x42);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0);
+        mbc.write_register(0x6000, 0x00);
+        mbc.write_register(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 30);
+
+        // No clock registers without an RTC
+        let mut mbc = Mbc3::new(false);
+        mbc.write_register(0x0000, 0x0A);
+        mbc.write_register(0x4000, 0x08);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+    }
+}
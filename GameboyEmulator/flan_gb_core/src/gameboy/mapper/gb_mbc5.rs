@@ -0,0 +1,104 @@
+use crate::gameboy::state::gb_state_io::{SaveStateError, StateReader, StateWriter};
+
+use super::gb_mapper::{banked_ram_offset, banked_rom_offset, Mapper};
+
+pub struct Mbc5 {
+    ram_enabled: bool,
+    rom_bank: u16, // 9 bits, unlike the other mappers bank 0 can be selected here
+    ram_bank: u8,
+}
+
+impl Mbc5 {
+    pub fn new() -> Mbc5 {
+        Mbc5 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Mbc5 {
+    fn rom_offset(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            banked_rom_offset(0, address)
+        } else {
+            banked_rom_offset(self.rom_bank as usize, address)
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0x0FF) | ((value as u16 & 0x01) << 8)
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => (),
+        }
+    }
+
+    fn ram_offset(&self, ram: &[u8], address: u16) -> Option<usize> {
+        match self.ram_enabled && !ram.is_empty() {
+            true => Some(banked_ram_offset(ram, self.ram_bank as usize, address)),
+            false => None,
+        }
+    }
+
+    fn write_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u16(self.rom_bank);
+        state.u8(self.ram_bank);
+    }
+
+    fn read_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = state.u16()?;
+        self.ram_bank = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_bank_switching() {
+        let mut mbc = Mbc5::new();
+        assert_eq!(mbc.rom_offset(0x4000), 0x4000);
+
+        // Bank 0 can be mapped to the second area too
+        mbc.write_register(0x2000, 0x00);
+        assert_eq!(mbc.rom_offset(0x4000), 0);
+
+        // The 9th bit comes from its own register
+        mbc.write_register(0x2FFF, 0x23);
+        mbc.write_register(0x3000, 0x01);
+        assert_eq!(mbc.rom_offset(0x4001), 0x123 * 0x4000 + 1);
+        mbc.write_register(0x2000, 0xFF);
+        assert_eq!(mbc.rom_offset(0x4000), 0x1FF * 0x4000);
+        mbc.write_register(0x3000, 0x00);
+        assert_eq!(mbc.rom_offset(0x4000), 0xFF * 0x4000);
+    }
+
+    #[test]
+    fn ram_bank_switching() {
+        let mut mbc = Mbc5::new();
+        let mut ram = vec![0; 0x20000];
+
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc.write_register(0x0000, 0x0A);
+        mbc.write_register(0x4000, 0x0F);
+        mbc.write_ram(&mut ram, 0xA003, 0x34);
+        assert_eq!(ram[0xF * 0x2000 + 3], 0x34);
+        assert_eq!(mbc.read_ram(&ram, 0xA003), 0x34);
+
+        // Banks past the end of the RAM wrap around
+        let small_ram = vec![0x56; 0x2000];
+        assert_eq!(mbc.read_ram(&small_ram, 0xA003), 0x56);
+    }
+}
@@ -0,0 +1,34 @@
+use crate::gameboy::state::gb_state_io::{SaveStateError, StateReader, StateWriter};
+
+use super::gb_mapper::{banked_ram_offset, Mapper};
+
+// Cartridges without a mapper: 32 KiB of ROM, and optionally up to 8 KiB of RAM
+pub struct RomOnly {}
+
+impl RomOnly {
+    pub fn new() -> RomOnly {
+        RomOnly {}
+    }
+}
+
+impl Mapper for RomOnly {
+    fn rom_offset(&self, address: u16) -> usize {
+        address as usize
+    }
+
+    fn write_register(&mut self, _address: u16, _value: u8) {}
+
+    fn ram_offset(&self, ram: &[u8], address: u16) -> Option<usize> {
+        match ram.is_empty() {
+            true => None,
+            false => Some(banked_ram_offset(ram, 0, address)),
+        }
+    }
+
+    // There are no registers to save
+    fn write_state(&self, _state: &mut StateWriter) {}
+
+    fn read_state(&mut self, _state: &mut StateReader) -> Result<(), SaveStateError> {
+        Ok(())
+    }
+}
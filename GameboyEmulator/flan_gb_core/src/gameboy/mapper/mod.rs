@@ -0,0 +1,6 @@
+pub mod gb_mapper;
+pub mod gb_mbc1;
+pub mod gb_mbc2;
+pub mod gb_mbc3;
+pub mod gb_mbc5;
+pub mod gb_rom_only;
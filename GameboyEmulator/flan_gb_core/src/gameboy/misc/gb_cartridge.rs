@@ -0,0 +1,92 @@
+use super::super::mapper::gb_mapper::create_mapper;
+use super::super::mapper::gb_mbc2::MBC2_RAM_SIZE;
+use super::super::GameBoy;
+use super::gb_misc::rom_hash;
+
+// Everything up to the end of the cartridge header
+const MIN_ROM_SIZE: usize = 0x150;
+
+impl GameBoy {
+    pub fn insert_cartridge(&mut self, path: &str) -> bool {
+        // Try to read the ROM file
+        let rom = match std::fs::read(path) {
+            Ok(bytes) => {
+                println!("ROM file loaded succesfully!");
+                bytes
+            }
+            Err(e) => {
+                println!("Unable to load ROM file \"{path}\", error message: {e}");
+                return false;
+            }
+        };
+        if !self.load_rom(rom) {
+            println!("Unable to load ROM file \"{path}\", it's too small to be a Game Boy ROM");
+            return false;
+        }
+
+        // If the cart has ERAM or a clock
+        if !self.eram.is_empty() || self.mapper.has_rtc() {
+            // Try to read the RAM file
+            self.save_path = path.replace(".gbc", ".gb").replace(".gb", ".sav");
+
+            // If it read succesfully, load that into ERAM, otherwise keep the freshly initialized ERAM
+            if let Ok(mut bytes) = std::fs::read(self.save_path.as_str()) {
+                // Anything past the end of ERAM is extra mapper state, like the RTC
+                if bytes.len() > self.eram.len() {
+                    let footer = bytes.split_off(self.eram.len());
+                    self.mapper.load_footer(&footer);
+                }
+                if !bytes.is_empty() {
+                    self.eram = bytes;
+                    if let Some(written) = &mut self.written_memory {
+                        written.mark_eram_written();
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Inserts a cartridge from ROM bytes in memory. No save file is loaded or written.
+    /// Returns false, without touching the Game Boy, if the ROM is too small to have a header.
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> bool {
+        if rom.len() < MIN_ROM_SIZE {
+            return false;
+        }
+        self.rom = rom;
+        self.rom_hash = rom_hash(&self.rom);
+
+        // Pick the mapper from the cartridge type
+        self.mapper = create_mapper(self.rom[0x147]);
+
+        // Run in CGB mode if the game supports it (0x80) or requires it (0xC0). A cartridge that was
+        // in before might have turned it on already
+        self.exit_cgb_mode();
+        if matches!(self.rom[0x143], 0x80 | 0xC0) {
+            self.init_cgb_mode();
+        }
+
+        // Get ERAM size, MBC2 has its RAM built in so the header says it has none
+        let eram_size = match (self.rom[0x147], self.rom[0x149]) {
+            (0x05 | 0x06, _) => MBC2_RAM_SIZE,
+            (_, 0) => 0,
+            (_, 1) => 2 * 1024,
+            (_, 2) => 8 * 1024,
+            (_, 3) => 32 * 1024,
+            (_, 4) => 128 * 1024,
+            (_, 5) => 64 * 1024,
+            _ => 128 * 1024, // fall back to 128
+        };
+
+        // Initialize ERAM the same way as the rest of memory
+        self.eram = vec![0xFF; eram_size];
+        self.memory_init
+            .fill(&mut self.eram, self.power_on_seed, 0xA000);
+        if let Some(written) = &mut self.written_memory {
+            written.reset_eram(eram_size);
+        }
+        self.save_path = "".to_string();
+        true
+    }
+}
@@ -0,0 +1,78 @@
+use super::super::misc::gb_poison::Ram;
+use super::super::GameBoy;
+
+impl GameBoy {
+    // Handle a write to HDMA5 (0xFF55)
+    pub(in super::super) fn start_hdma(&mut self, value: u8) {
+        // Writing with bit 7 cleared while an H-blank DMA is running stops it
+        if self.hdma_active && value & 0x80 == 0 {
+            self.hdma_active = false;
+            return;
+        }
+
+        // Get the addresses from HDMA1-4. The source is anywhere in memory, the destination is always in VRAM
+        self.hdma_source = ((self.io[0x51] as u16) << 8 | self.io[0x52] as u16) & 0xFFF0;
+        self.hdma_destination =
+            (((self.io[0x53] as u16) << 8 | self.io[0x54] as u16) & 0x1FF0) | 0x8000;
+        self.hdma_blocks_left = (value & 0x7F) + 1;
+
+        if value & 0x80 == 0 {
+            // General purpose DMA, copy everything right now
+            while self.hdma_blocks_left > 0 {
+                self.copy_hdma_block();
+            }
+        } else {
+            // H-blank DMA, the PPU will call copy_hdma_block every H-blank
+            self.hdma_active = true;
+        }
+    }
+
+    // Copy 16 bytes to VRAM, and stall the CPU while doing so
+    pub(in super::super) fn copy_hdma_block(&mut self) {
+        for _ in 0..16 {
+            // The DMA reads the bus itself, so this doesn't go through the CPU's read path
+            let byte = self.peek_byte_from_memory(self.hdma_source);
+            let offset = self.vram_bank * 0x2000 + (self.hdma_destination & 0x1FFF) as usize;
+            self.vram[offset] = byte;
+            self.mark_dma_written(Ram::Vram, offset);
+            self.hdma_source = self.hdma_source.wrapping_add(1);
+            self.hdma_destination = 0x8000 | (self.hdma_destination.wrapping_add(1) & 0x1FFF);
+        }
+
+        // The CPU is stalled while the block is copied. A block takes 8 cycles in normal speed, and 16 in double
+        // speed since the transfer speed doesn't change
+        self.curr_cycles_to_wait += if self.double_speed { 16 } else { 8 };
+
+        self.hdma_blocks_left -= 1;
+        if self.hdma_blocks_left == 0 {
+            self.hdma_active = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn general_purpose_dma_stalls_only_for_the_copy() {
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x143] = 0x80;
+        for (i, byte) in rom[0x4000..0x4020].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let mut game_boy = GameBoy::with_power_on_seed(1);
+        assert!(game_boy.load_rom(rom));
+        game_boy.skip_boot_rom();
+
+        // Copy 2 blocks from 0x4000 to 0x8100
+        game_boy.io[0x51..0x55].copy_from_slice(&[0x40, 0x00, 0x01, 0x00]);
+        game_boy.curr_cycles_to_wait = 0;
+        game_boy.start_hdma(0x01);
+        assert_eq!(game_boy.curr_cycles_to_wait, 16);
+        assert_eq!(
+            &game_boy.vram[0x0100..0x0120],
+            &game_boy.rom[0x4000..0x4020]
+        );
+    }
+}
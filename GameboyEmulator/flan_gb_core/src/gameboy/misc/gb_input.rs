@@ -0,0 +1,75 @@
+use super::super::cpu::gb_interrupts::InterruptMasks;
+use crate::gameboy::{GameBoy, InputState};
+
+impl InputState {
+    /// Lets go of both directions when opposite ones are held together, like up and down. The
+    /// d-pad can't physically do that, and some games glitch out when it happens.
+    pub fn without_opposite_directions(mut self) -> InputState {
+        if self.up && self.down {
+            self.up = false;
+            self.down = false;
+        }
+        if self.left && self.right {
+            self.left = false;
+            self.right = false;
+        }
+        self
+    }
+}
+
+impl GameBoy {
+    /// Sets which buttons are held. It can be called at any time, even between instructions, and
+    /// the game sees the change the next time it reads P1 (0xFF00).
+    pub fn update_input(&mut self, state: &InputState) {
+        let mut new_state: u8 = 0;
+        if !state.down {
+            new_state |= 1 << 7
+        }
+        if !state.up {
+            new_state |= 1 << 6
+        }
+        if !state.left {
+            new_state |= 1 << 5
+        }
+        if !state.right {
+            new_state |= 1 << 4
+        }
+        if !state.start {
+            new_state |= 1 << 3
+        }
+        if !state.select {
+            new_state |= 1 << 2
+        }
+        if !state.b {
+            new_state |= 1 << 1
+        }
+        if !state.a {
+            new_state |= 1 << 0
+        }
+
+        let old_lines = self.joypad_lines();
+        self.joypad_state = new_state;
+        self.check_joypad_edges(old_lines);
+    }
+
+    // P10-P13 as the game sees them in the low nibble of P1. Every group with its select line low
+    // pulls its pressed buttons low, so with both selected the lines are the AND of the two.
+    pub(in super::super) fn joypad_lines(&self) -> u8 {
+        let mut lines = 0x0F;
+        if self.io[0x00] & (1 << 4) == 0 {
+            lines &= self.joypad_state >> 4;
+        }
+        if self.io[0x00] & (1 << 5) == 0 {
+            lines &= self.joypad_state & 0x0F;
+        }
+        lines
+    }
+
+    // Any of P10-P13 going from high to low requests the joypad interrupt, and wakes the CPU up from STOP
+    pub(in super::super) fn check_joypad_edges(&mut self, old_lines: u8) {
+        if old_lines & !self.joypad_lines() & 0x0F != 0 {
+            self.io[0x0F] |= InterruptMasks::Joypad as u8;
+            self.is_stopped = false;
+        }
+    }
+}
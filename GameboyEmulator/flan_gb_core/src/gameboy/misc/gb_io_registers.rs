@@ -21,8 +21,15 @@ impl GameBoy {
     pub(in super::super) fn handle_io_register_write(&mut self, address: u16, value: u8) -> bool {
         // TODO: actually implement registers
         match address {
-            0xFF00 => self.io[0x00] = (value & 0b00110000) | (self.io[0x00] & 0b11001111),
+            0xFF00 => {
+                // Selecting a group with a button already held pulls a line low too
+                let old_lines = self.joypad_lines();
+                self.io[0x00] = (value & 0b00110000) | (self.io[0x00] & 0b11001111);
+                self.check_joypad_edges(old_lines);
+            }
             0xFF04 => self.timer_div = 0x0000,
+            0xFF01 => self.write_serial_data(value),
+            0xFF02 => self.write_serial_control(value),
             0xFF11 => {
                 self.io[0x11] = value;
                 self.apu_pulse1_length_timer = 64 - (value & 0b00111111);
@@ -110,6 +117,30 @@ impl GameBoy {
                 self.oam_dma_source = (value as u16) << 8;
             }
             0xFF50 => self.rom_chip_enabled = false,
+
+            // CGB registers
+            0xFF4D if self.cgb_mode => self.io[0x4D] = value & 0x01,
+            0xFF4F if self.cgb_mode => self.vram_bank = (value & 0x01) as usize,
+            0xFF55 if self.cgb_mode => self.start_hdma(value),
+            0xFF70 if self.cgb_mode => {
+                // Bank 0 can not be mapped to 0xD000, it turns into bank 1
+                self.wram_bank = (value & 0x07).max(1) as usize;
+            }
+            0xFF68 | 0xFF6A if self.cgb_mode => self.io[(address & 0x7F) as usize] = value | 0x40,
+            0xFF69 if self.cgb_mode => {
+                let index = self.io[0x68] & 0x3F;
+                if self.ppu_mode != 3 {
+                    self.bg_palette_ram[index as usize] = value;
+                }
+                self.io[0x68] = increment_palette_index(self.io[0x68]);
+            }
+            0xFF6B if self.cgb_mode => {
+                let index = self.io[0x6A] & 0x3F;
+                if self.ppu_mode != 3 {
+                    self.obj_palette_ram[index as usize] = value;
+                }
+                self.io[0x6A] = increment_palette_index(self.io[0x6A]);
+            }
             _ => self.io[(address & 0x7F) as usize] = value,
         }
         true
@@ -117,18 +148,42 @@ impl GameBoy {
 
     pub(in super::super) fn handle_io_register_read(&self, address: u16) -> u8 {
         match address {
-            0xFF00 => {
-                let mut return_value = self.io[0x00] & 0xF0;
-                if return_value & (1 << 4) == 0 {
-                    return_value |= self.joypad_state >> 4;
-                } else if return_value & (1 << 5) == 0 {
-                    return_value |= self.joypad_state & 0x0F;
-                } else {
-                    return_value |= 0x0F;
+            0xFF00 => (self.io[0x00] & 0xF0) | self.joypad_lines(),
+
+            // Gameboy Doctor logs are made with LY stuck at $90, so traces only line up if we do the same
+            0xFF44 if self.trace_log.is_some() => 0x90,
+
+            // CGB registers
+            0xFF4D if self.cgb_mode => {
+                ((self.double_speed as u8) << 7) | 0x7E | (self.io[0x4D] & 0x01)
+            }
+            0xFF4F if self.cgb_mode => 0xFE | self.vram_bank as u8,
+            0xFF55 if self.cgb_mode => {
+                // Bit 7 is cleared while an H-blank DMA is running, the rest is the remaining length minus 1
+                let remaining = self.hdma_blocks_left.wrapping_sub(1) & 0x7F;
+                match self.hdma_active {
+                    true => remaining,
+                    false => 0x80 | remaining,
                 }
-                return_value
-            } // TODO: actually implement input
+            }
+            0xFF70 if self.cgb_mode => 0xF8 | self.wram_bank as u8,
+            0xFF69 if self.cgb_mode => match self.ppu_mode {
+                3 => 0xFF,
+                _ => self.bg_palette_ram[(self.io[0x68] & 0x3F) as usize],
+            },
+            0xFF6B if self.cgb_mode => match self.ppu_mode {
+                3 => 0xFF,
+                _ => self.obj_palette_ram[(self.io[0x6A] & 0x3F) as usize],
+            },
             _ => self.io[(address & 0x7F) as usize],
         }
     }
 }
+
+// If the auto-increment bit (7) of BCPS/OCPS is set, move the index (bits 0-5) to the next byte
+fn increment_palette_index(specification: u8) -> u8 {
+    if specification & 0x80 == 0 {
+        return specification;
+    }
+    (specification & 0xC0) | ((specification + 1) & 0x3F)
+}
@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+use super::gb_serial::SerialDevice;
+
+// Messages over TCP are 2 bytes, a kind and a value
+const MESSAGE_WAITING: u8 = 0;
+const MESSAGE_NOT_WAITING: u8 = 1;
+const MESSAGE_DATA: u8 = 2;
+
+/// One end of a link cable between two Game Boys, either in the same process or over TCP.
+/// Plug it in with `GameBoy::connect_serial`.
+pub struct LinkCable {
+    transport: Transport,
+}
+
+enum Transport {
+    Local {
+        ends: Arc<Mutex<[CableEnd; 2]>>,
+        side: usize,
+    },
+    Tcp {
+        stream: TcpStream,
+        peer_waiting: Option<u8>,
+        received: VecDeque<u8>,
+        partial_message: Vec<u8>,
+    },
+    Disconnected,
+}
+
+// What one side of an in-process cable is offering, and what was clocked in to it
+#[derive(Default)]
+struct CableEnd {
+    waiting: Option<u8>,
+    received: Option<u8>,
+}
+
+impl LinkCable {
+    /// A cable with both ends in this process
+    pub fn pair() -> (LinkCable, LinkCable) {
+        let ends = Arc::new(Mutex::new([CableEnd::default(), CableEnd::default()]));
+        let end = |side| LinkCable {
+            transport: Transport::Local {
+                ends: ends.clone(),
+                side,
+            },
+        };
+        (end(0), end(1))
+    }
+
+    /// Waits for another emulator to connect to this address
+    pub fn listen(address: impl ToSocketAddrs) -> io::Result<LinkCable> {
+        let (stream, _) = TcpListener::bind(address)?.accept()?;
+        LinkCable::from_stream(stream)
+    }
+
+    /// Connects to another emulator that's listening on this address
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<LinkCable> {
+        LinkCable::from_stream(TcpStream::connect(address)?)
+    }
+
+    fn from_stream(stream: TcpStream) -> io::Result<LinkCable> {
+        // Every message is tiny and time sensitive, so send them straight away
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(LinkCable {
+            transport: Transport::Tcp {
+                stream,
+                peer_waiting: None,
+                received: VecDeque::new(),
+                partial_message: Vec::new(),
+            },
+        })
+    }
+
+    fn send(&mut self, kind: u8, value: u8) {
+        let Transport::Tcp { stream, .. } = &mut self.transport else {
+            return;
+        };
+        if let Err(e) = write_all_nonblocking(stream, &[kind, value]) {
+            println!("Link cable disconnected: {e}");
+            self.transport = Transport::Disconnected;
+        }
+    }
+
+    // Handle everything the other side sent since last time
+    fn receive_messages(&mut self) {
+        let Transport::Tcp {
+            stream,
+            peer_waiting,
+            received,
+            partial_message,
+        } = &mut self.transport
+        else {
+            return;
+        };
+
+        let mut buffer = [0u8; 256];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => {
+                    println!("Link cable disconnected");
+                    self.transport = Transport::Disconnected;
+                    return;
+                }
+                Ok(length) => partial_message.extend_from_slice(&buffer[..length]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    println!("Link cable disconnected: {e}");
+                    self.transport = Transport::Disconnected;
+                    return;
+                }
+            }
+        }
+
+        let complete = partial_message.len() / 2 * 2;
+        for message in partial_message[..complete].chunks(2) {
+            match message[0] {
+                MESSAGE_WAITING => *peer_waiting = Some(message[1]),
+                MESSAGE_NOT_WAITING => *peer_waiting = None,
+                MESSAGE_DATA => received.push_back(message[1]),
+                _ => println!("Unknown link cable message {:02X}", message[0]),
+            }
+        }
+        partial_message.drain(..complete);
+    }
+}
+
+impl SerialDevice for LinkCable {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.receive_messages();
+        match &mut self.transport {
+            Transport::Local { ends, side } => {
+                // The other side only shifts if it's waiting for a transfer
+                let mut ends = ends.lock().unwrap();
+                let peer = &mut ends[1 - *side];
+                match peer.waiting.take() {
+                    Some(peer_byte) => {
+                        peer.received = Some(byte);
+                        peer_byte
+                    }
+                    None => 0xFF,
+                }
+            }
+            Transport::Tcp { peer_waiting, .. } => match peer_waiting.take() {
+                Some(peer_byte) => {
+                    self.send(MESSAGE_DATA, byte);
+                    peer_byte
+                }
+                None => 0xFF,
+            },
+            Transport::Disconnected => 0xFF,
+        }
+    }
+
+    fn set_waiting_byte(&mut self, byte: Option<u8>) {
+        match &mut self.transport {
+            Transport::Local { ends, side } => ends.lock().unwrap()[*side].waiting = byte,
+            Transport::Tcp { .. } => match byte {
+                Some(byte) => self.send(MESSAGE_WAITING, byte),
+                None => self.send(MESSAGE_NOT_WAITING, 0),
+            },
+            Transport::Disconnected => (),
+        }
+    }
+
+    fn poll_received(&mut self) -> Option<u8> {
+        self.receive_messages();
+        match &mut self.transport {
+            Transport::Local { ends, side } => ends.lock().unwrap()[*side].received.take(),
+            Transport::Tcp { received, .. } => received.pop_front(),
+            Transport::Disconnected => None,
+        }
+    }
+}
+
+// The socket is non-blocking for reads, but messages should never be split up on the way out
+fn write_all_nonblocking(stream: &mut TcpStream, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        match stream.write(data) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(length) => data = &data[length..],
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
+                std::thread::yield_now()
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
@@ -1,25 +1,45 @@
 use std::collections::VecDeque;
 use std::{env, fs};
 
-use crate::DEBUG_WIDTH;
 use rand::Rng;
-use rodio::{OutputStream, Sink};
 
+use super::super::apu::gb_audio_synth::{AudioMode, AudioSynth};
+use super::super::debug::gb_debugger::Debugger;
+use super::super::mapper::gb_mapper::create_mapper;
 use super::super::GameBoy;
+use super::gb_poison::WrittenMemory;
+use super::gb_power_on::MemoryInit;
+use crate::DEFAULT_AUDIO_SAMPLE_RATE;
+
+impl Default for GameBoy {
+    fn default() -> Self {
+        GameBoy::new()
+    }
+}
 
 impl GameBoy {
-    pub(crate) fn new() -> GameBoy {
+    /// Creates a Game Boy whose memory powers on with random contents, like the real hardware
+    pub fn new() -> GameBoy {
+        GameBoy::with_power_on_seed(rand::thread_rng().gen())
+    }
+
+    /// Creates a Game Boy whose memory powers on with contents picked by `seed`. The same seed
+    /// and ROM always power on the same way, which is what makes movies play back exactly.
+    pub fn with_power_on_seed(seed: u64) -> GameBoy {
         // Create the Game Boy object
-        let (stream, stream_device) = OutputStream::try_default().unwrap();
         let mut new_game_boy = GameBoy {
             bios: [0xFF; 0x100],
             rom: Vec::new(),
+            rom_hash: rom_hash(&[]),
             eram: Vec::new(),
-            vram: [0xFF; 0x2000],
-            wram: [0xFF; 0x2000],
+            mapper: create_mapper(0x00),
+            vram: [0xFF; 0x4000],
+            wram: [0xFF; 0x8000],
             oam: [0xFF; 0xA0],
             io: [0xFF; 0x80],
             hram: [0xFF; 0x7F],
+            bg_palette_ram: [0xFF; 0x40],
+            obj_palette_ram: [0xFF; 0x40],
             ie: 0,
             ime: 0,
             pc: 0,
@@ -35,12 +55,11 @@ impl GameBoy {
             ppu_pixels_to_discard: 0,
             ppu_sprite_buffer: Vec::new(),
             framebuffer: vec![0; 160 * 144],
-            apu_stream: stream,
-            //apu_stream_handle: stream_device,
             apu_buffer: [[0; 512]; 2],
             apu_buffer_to_use: 0,
             apu_buffer_write_index: 0,
             apu_buffer_read_index: 0,
+            apu_output: Vec::new(),
             apu_sound_output: [0, 0, 0, 0],
             apu_pulse1_freq_counter: 0,
             apu_pulse1_env_counter: 0,
@@ -70,17 +89,26 @@ impl GameBoy {
             reg_e: 0,
             reg_h: 0,
             reg_l: 0,
+            cgb_mode: false,
+            vram_bank: 0,
+            wram_bank: 1,
+            double_speed: false,
+            double_speed_skip_cycle: false,
+            hdma_source: 0,
+            hdma_destination: 0,
+            hdma_blocks_left: 0,
+            hdma_active: false,
             times: [0xFF; 0x100],
             curr_cycles_to_wait: 0,
             last_opcode: 0x00,
             last_opcode_cycles: 0,
+            instruction_pc: 0,
             new_instruction_tick: false,
             rom_chip_enabled: true,
-            eram_chip_enabled: false,
-            curr_rom_bank: 1,
-            curr_eram_bank: 0,
             cpu_cycle_counter: 0,
+            elapsed_dots: 0,
             is_halted: false,
+            is_stopped: false,
             timer_div: 0,
             timer_overflow: false,
             oam_dma_counter: 0,
@@ -88,9 +116,19 @@ impl GameBoy {
             joypad_state: 0xFF,
             window_is_rendering: false,
             save_path: "".to_string(),
-            debug_enabled: false,
-            debug_bios: false,
-            debug_require_input: false,
+            power_on_seed: seed,
+            memory_init: MemoryInit::default(),
+            written_memory: None,
+            serial_device: None,
+            serial_cycles_left: 0,
+            serial_poll_timer: 0,
+            serial_output: None,
+            test_bus: None,
+            debugger: Debugger::default(),
+            trace_log: None,
+            audio_mode: AudioMode::default(),
+            audio_synth: AudioSynth::new(DEFAULT_AUDIO_SAMPLE_RATE),
+            audio_recorder: None,
             apu_pulse1_sweep_timer: 0,
             apu_pulse1_sweep_shadow_freq: 0,
             apu_pulse1_sweep_enable: false,
@@ -99,31 +137,10 @@ impl GameBoy {
             apu_wave_duty_step: 0,
             apu_wave_length_timer: 0,
             apu_wave_enabled: false,
-            apu_sink: Sink::try_new(&stream_device).unwrap(),
         };
 
-        // Init RNG
-        let mut rng = rand::thread_rng();
-
-        // Randomize VRAM
-        for value in &mut new_game_boy.vram {
-            *value = rng.gen_range(0..=255) as u8;
-        }
-
-        // Randomize WRAM
-        for value in &mut new_game_boy.wram {
-            *value = rng.gen_range(0..=255) as u8;
-        }
-
-        // Randomize OAM
-        for value in &mut new_game_boy.oam {
-            *value = rng.gen_range(0..=255) as u8;
-        }
-
-        // Randomize HRAM
-        for value in &mut new_game_boy.hram {
-            *value = rng.gen_range(0..=255) as u8;
-        }
+        // Randomize VRAM, WRAM, OAM and HRAM, each from the seed and its own address
+        new_game_boy.set_memory_init(MemoryInit::default());
 
         // Load boot rom file
         let mut dmg_boot_path = env::current_exe().unwrap();
@@ -146,7 +163,121 @@ impl GameBoy {
         new_game_boy
     }
 
-    pub(crate) fn print_reg_state(&self) {
+    /// Fills VRAM, WRAM, OAM and HRAM again the way `memory_init` says. Call it before inserting
+    /// the cartridge, which fills its RAM the same way unless there's a save file.
+    pub fn set_memory_init(&mut self, memory_init: MemoryInit) {
+        self.memory_init = memory_init;
+        memory_init.fill(&mut self.vram, self.power_on_seed, 0x8000);
+        memory_init.fill(&mut self.wram, self.power_on_seed, 0xC000);
+        memory_init.fill(&mut self.oam, self.power_on_seed, 0xFE00);
+        memory_init.fill(&mut self.hram, self.power_on_seed, 0xFF80);
+
+        // Poison starts keeping track of what's been written from here on
+        self.written_memory = match memory_init {
+            MemoryInit::Poison => Some(WrittenMemory::new(self.eram.len())),
+            _ => None,
+        };
+    }
+
+    /// Turns the Game Boy off and on again, with the same power-on seed and memory init. The cartridge stays in
+    /// with its save data, and so does everything set up from outside, like the serial device,
+    /// debugger and audio settings.
+    pub fn reset(&mut self) {
+        let mut fresh = GameBoy::with_power_on_seed(self.power_on_seed);
+        fresh.set_memory_init(self.memory_init);
+
+        // Put the same cartridge back in, RAM and clock included
+        if !self.rom.is_empty() {
+            // It loaded before, so it loads again
+            fresh.load_rom(std::mem::take(&mut self.rom));
+            fresh.eram = std::mem::take(&mut self.eram);
+            if let (Some(written), Some(old_written)) =
+                (&mut fresh.written_memory, &self.written_memory)
+            {
+                written.copy_eram_from(old_written);
+            }
+            fresh.mapper.load_footer(&self.mapper.save_footer());
+            fresh.save_path = std::mem::take(&mut self.save_path);
+        }
+
+        // Keep everything plugged in, and keep counting time so frontends can keep pacing by it
+        fresh.serial_device = self.serial_device.take();
+        fresh.test_bus = self.test_bus.take();
+        fresh.debugger = std::mem::take(&mut self.debugger);
+        fresh.trace_log = self.trace_log.take();
+        fresh.audio_mode = self.audio_mode;
+        fresh.audio_synth = std::mem::replace(
+            &mut self.audio_synth,
+            AudioSynth::new(DEFAULT_AUDIO_SAMPLE_RATE),
+        );
+        fresh.audio_synth.reset();
+        fresh.audio_recorder = self.audio_recorder.take();
+        fresh.elapsed_dots = self.elapsed_dots;
+
+        *self = fresh;
+    }
+
+    // Puts the CGB-only state back to how a DMG game expects it
+    pub(in super::super) fn exit_cgb_mode(&mut self) {
+        self.cgb_mode = false;
+        self.vram_bank = 0;
+        self.wram_bank = 1;
+        self.double_speed = false;
+        self.double_speed_skip_cycle = false;
+        self.hdma_blocks_left = 0;
+        self.hdma_active = false;
+    }
+
+    pub(in super::super) fn init_cgb_mode(&mut self) {
+        self.cgb_mode = true;
+
+        // We don't have a CGB boot ROM, so skip straight to the state it leaves the CPU in
+        self.rom_chip_enabled = false;
+        self.pc = 0x0100;
+        self.sp = 0xFFFE;
+        self.reg_a = 0x11; // Games check this value to see if they are running on a CGB
+        self.reg_f = 0x80;
+        self.reg_b = 0x00;
+        self.reg_c = 0x00;
+        self.reg_d = 0xFF;
+        self.reg_e = 0x56;
+        self.reg_h = 0x00;
+        self.reg_l = 0x0D;
+
+        // The boot ROM turns on the LCD, and leaves all the background palettes white
+        self.io[0x40] = 0x91;
+        self.io[0x68] = 0xC0;
+        self.io[0x6A] = 0xC0;
+        self.bg_palette_ram = [0xFF; 0x40];
+    }
+
+    /// Jumps straight to the cartridge entry point, with the registers the DMG boot ROM leaves behind.
+    /// CGB games already start there.
+    pub fn skip_boot_rom(&mut self) {
+        if !self.rom_chip_enabled {
+            return;
+        }
+
+        self.rom_chip_enabled = false;
+        self.pc = 0x0100;
+        self.sp = 0xFFFE;
+        self.reg_a = 0x01;
+        self.reg_f = 0xB0;
+        self.reg_b = 0x00;
+        self.reg_c = 0x13;
+        self.reg_d = 0x00;
+        self.reg_e = 0xD8;
+        self.reg_h = 0x01;
+        self.reg_l = 0x4D;
+        self.timer_div = 0xABCC;
+        self.io[0x04] = 0xAB;
+
+        // The boot ROM turns on the LCD and sets up the background palette
+        self.io[0x40] = 0x91;
+        self.io[0x47] = 0xFC;
+    }
+
+    pub fn print_reg_state(&self) {
         println!("AF: {:02X} {:02X}", self.reg_a, self.reg_f);
         println!("BC: {:02X} {:02X}", self.reg_b, self.reg_c);
         println!("DE: {:02X} {:02X}", self.reg_d, self.reg_e);
@@ -169,38 +300,39 @@ impl GameBoy {
         );
     }
 
-    pub(crate) fn dump_memory(&mut self, file_path: &str, memory_start: u16, dump_length: u16) {
+    pub fn dump_memory(&self, file_path: &str, memory_start: u16, dump_length: u16) {
         // Get Vec<u8> of all the bytes in the range specified
-        let mut bytes: Vec<u8> = Vec::new();
-        bytes.reserve(dump_length as usize);
+        let mut bytes: Vec<u8> = Vec::with_capacity(dump_length as usize);
         for x in memory_start..(memory_start + dump_length) {
-            bytes.push(self.fetch_byte_from_memory(x));
+            bytes.push(self.peek_byte_from_memory(x));
         }
 
         // Dump to file
         fs::write(file_path, bytes).unwrap();
     }
 
-    pub(crate) fn render_memory(
-        &mut self,
-        buffer: &mut Vec<u32>,
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_memory(
+        &self,
+        buffer: &mut [u32],
         memory_start: usize,
         tile_w: usize,
         tile_h: usize,
         offset_x: usize,
         offset_y: usize,
         pixel_scale: usize,
+        width: usize,
     ) {
         // Render outline
         let end_x = offset_x + tile_w * 8 * pixel_scale;
         let end_y = offset_y + tile_h * 8 * pixel_scale;
         for x in (offset_x - 1)..=(end_x + 1) {
-            buffer[x + (offset_y - 1) * DEBUG_WIDTH] = 0xFFFF00FF;
-            buffer[x + (end_y + 1) * DEBUG_WIDTH] = 0xFFFF00FF;
+            buffer[x + (offset_y - 1) * width] = 0xFFFF00FF;
+            buffer[x + (end_y + 1) * width] = 0xFFFF00FF;
         }
         for y in (offset_y - 1)..=(end_y + 1) {
-            buffer[(offset_x - 1) + y * DEBUG_WIDTH] = 0xFFFF00FF;
-            buffer[(end_x + 1) + y * DEBUG_WIDTH] = 0xFFFF00FF;
+            buffer[(offset_x - 1) + y * width] = 0xFFFF00FF;
+            buffer[(end_x + 1) + y * width] = 0xFFFF00FF;
         }
 
         let ram_base = memory_start;
@@ -216,9 +348,8 @@ impl GameBoy {
 
                     // Get the 2 bytes for the pixel row, where row_1 is the LSB and row_2 the MSB
                     // This means if only row_1's bit is set, the colour is dark grey
-                    let row_1 = self.fetch_byte_from_memory(tile_address);
-                    let row_2 = self.fetch_byte_from_memory(tile_address + 1);
-                    self.curr_cycles_to_wait -= 2;
+                    let row_1 = self.peek_byte_from_memory(tile_address);
+                    let row_2 = self.peek_byte_from_memory(tile_address + 1);
 
                     for pixel_x in 0..8 {
                         // Calculate pixel brightness from 0 to 3
@@ -241,7 +372,7 @@ impl GameBoy {
                                 // Calculate buffer index for this pixel
                                 let buffer_x = (tile_x * 8 + pixel_x) * pixel_scale + x + offset_x;
                                 let buffer_y = (tile_y * 8 + pixel_y) * pixel_scale + y + offset_y;
-                                let buffer_index = buffer_x + buffer_y * DEBUG_WIDTH;
+                                let buffer_index = buffer_x + buffer_y * width;
 
                                 // Set the pixel in the buffer
                                 buffer[buffer_index] = brightness;
@@ -254,6 +385,16 @@ impl GameBoy {
     }
 }
 
+// 64-bit FNV-1a hash, used to make sure save states and the like belong to the ROM they're loaded into
+pub(in super::super) fn rom_hash(rom: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF29CE484222325;
+    for byte in rom {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001B3);
+    }
+    hash
+}
+
 const FLAN_BOOT_ROM: [u8; 256] = [
     0x31, 0xFE, 0xFF, 0xAF, 0x21, 0xFF, 0x9F, 0x32, 0xCB, 0x7C, 0x20, 0xFB, 0x11, 0x9E, 0x00, 0x21,
     0x10, 0x80, 0x1A, 0x22, 0x22, 0x1C, 0x20, 0xFA, 0x21, 0xE8, 0x98, 0x01, 0x1C, 0x00, 0x3E, 0x01,
@@ -0,0 +1,90 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    Png(String),
+    SizeMismatch {
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{e}"),
+            ImageError::Png(e) => write!(f, "invalid PNG: {e}"),
+            ImageError::SizeMismatch { expected, found } => write!(
+                f,
+                "image is {}x{}, expected {}x{} or a whole multiple of it",
+                found.0, found.1, expected.0, expected.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> Self {
+        ImageError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for ImageError {
+    fn from(e: png::EncodingError) -> Self {
+        match e {
+            png::EncodingError::IoError(e) => ImageError::Io(e),
+            e => ImageError::Png(e.to_string()),
+        }
+    }
+}
+
+impl From<png::DecodingError> for ImageError {
+    fn from(e: png::DecodingError) -> Self {
+        match e {
+            png::DecodingError::IoError(e) => ImageError::Io(e),
+            e => ImageError::Png(e.to_string()),
+        }
+    }
+}
+
+// Writes 8-bit grayscale or RGB pixels, depending on the colour type
+pub(in super::super) fn write_png(
+    path: &Path,
+    width: usize,
+    height: usize,
+    color: png::ColorType,
+    pixels: &[u8],
+) -> Result<(), ImageError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+    Ok(())
+}
+
+// Reads any PNG as 8-bit RGB, returns the width, height and pixels
+pub(in super::super) fn read_png_rgb(path: &Path) -> Result<(usize, usize, Vec<u8>), ImageError> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+
+    let pixels = match info.color_type {
+        png::ColorType::Grayscale => buffer.iter().flat_map(|v| [*v, *v, *v]).collect(),
+        png::ColorType::GrayscaleAlpha => {
+            buffer.chunks(2).flat_map(|p| [p[0], p[0], p[0]]).collect()
+        }
+        png::ColorType::Rgba => buffer.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+        _ => buffer,
+    };
+    Ok((info.width as usize, info.height as usize, pixels))
+}
@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+
+use super::super::GameBoy;
+
+#[derive(Clone, Copy)]
+pub(in super::super) enum Ram {
+    Vram,
+    Eram,
+    Wram,
+    Oam,
+    Hram,
+}
+
+// Which bytes of RAM have been written since power-on, for the poison memory init mode
+pub(in super::super) struct WrittenMemory {
+    vram: Vec<bool>,
+    eram: Vec<bool>,
+    wram: Vec<bool>,
+    oam: Vec<bool>,
+    hram: Vec<bool>,
+    // Each instruction reading each address is only logged once, or loops would flood the log
+    reported: HashSet<(u16, u16)>,
+}
+
+impl WrittenMemory {
+    pub(in super::super) fn new(eram_size: usize) -> WrittenMemory {
+        WrittenMemory {
+            vram: vec![false; 0x4000],
+            eram: vec![false; eram_size],
+            wram: vec![false; 0x8000],
+            oam: vec![false; 0xA0],
+            hram: vec![false; 0x7F],
+            reported: HashSet::new(),
+        }
+    }
+
+    // A new cartridge starts out with none of its RAM written
+    pub(in super::super) fn reset_eram(&mut self, eram_size: usize) {
+        self.eram = vec![false; eram_size];
+    }
+
+    // The cartridge RAM keeps its contents over a reset, and so it stays written
+    pub(in super::super) fn copy_eram_from(&mut self, other: &WrittenMemory) {
+        self.eram.clone_from(&other.eram);
+    }
+
+    // RAM that came from a save file counts as written
+    pub(in super::super) fn mark_eram_written(&mut self) {
+        self.eram.fill(true);
+    }
+
+    pub(in super::super) fn mark_all_written(&mut self) {
+        for ram in [Ram::Vram, Ram::Eram, Ram::Wram, Ram::Oam, Ram::Hram] {
+            self.memory(ram).fill(true);
+        }
+    }
+
+    fn memory(&mut self, ram: Ram) -> &mut Vec<bool> {
+        match ram {
+            Ram::Vram => &mut self.vram,
+            Ram::Eram => &mut self.eram,
+            Ram::Wram => &mut self.wram,
+            Ram::Oam => &mut self.oam,
+            Ram::Hram => &mut self.hram,
+        }
+    }
+}
+
+impl GameBoy {
+    pub(in super::super) fn mark_ram_written(&mut self, address: u16) {
+        let Some((ram, offset)) = self.ram_location(address) else {
+            return;
+        };
+        if let Some(written) = &mut self.written_memory {
+            written.memory(ram)[offset] = true;
+        }
+    }
+
+    // Logs a read of RAM that was never written, once per instruction and address
+    pub(in super::super) fn check_ram_read(&mut self, address: u16) {
+        let Some((ram, offset)) = self.ram_location(address) else {
+            return;
+        };
+        let pc = self.instruction_pc;
+        let pc_bank = self.rom_bank_at(pc);
+        let Some(written) = &mut self.written_memory else {
+            return;
+        };
+        if written.memory(ram)[offset] || !written.reported.insert((pc, address)) {
+            return;
+        }
+
+        let (name, bank) = match ram {
+            Ram::Vram => ("VRAM", Some(offset / 0x2000)),
+            Ram::Eram => ("ERAM", Some(offset / 0x2000)),
+            Ram::Wram => ("WRAM", Some(offset / 0x1000)),
+            Ram::Oam => ("OAM", None),
+            Ram::Hram => ("HRAM", None),
+        };
+        let bank = match bank {
+            Some(bank) => format!(" (bank {bank})"),
+            None => String::new(),
+        };
+        let pc = match pc_bank {
+            Some(pc_bank) => format!("{pc_bank:02X}:{pc:04X}"),
+            None => format!("${pc:04X}"),
+        };
+        println!("Read of uninitialized {name} ${address:04X}{bank} at {pc}");
+    }
+
+    // Where a CPU access to this address ends up, or None if it isn't RAM or the RAM can't be accessed right now
+    fn ram_location(&self, address: u16) -> Option<(Ram, usize)> {
+        if self.test_bus.is_some() {
+            return None;
+        }
+        match address {
+            0x8000..=0x9FFF if self.ppu_mode != 3 => Some((
+                Ram::Vram,
+                self.vram_bank * 0x2000 + (address & 0x1FFF) as usize,
+            )),
+            0xA000..=0xBFFF => self
+                .mapper
+                .ram_offset(&self.eram, address)
+                .map(|offset| (Ram::Eram, offset)),
+            0xC000..=0xCFFF | 0xE000..=0xEFFF => Some((Ram::Wram, (address & 0x0FFF) as usize)),
+            0xD000..=0xDFFF | 0xF000..=0xFDFF => Some((
+                Ram::Wram,
+                self.wram_bank * 0x1000 + (address & 0x0FFF) as usize,
+            )),
+            0xFE00..=0xFE9F if self.ppu_mode < 2 => Some((Ram::Oam, (address & 0xFF) as usize)),
+            0xFF80..=0xFFFE => Some((Ram::Hram, (address & 0x7F) as usize)),
+            _ => None,
+        }
+    }
+
+    // DMA writes straight into VRAM and OAM, without going through the CPU's view of memory
+    pub(in super::super) fn mark_dma_written(&mut self, ram: Ram, offset: usize) {
+        if let Some(written) = &mut self.written_memory {
+            written.memory(ram)[offset] = true;
+        }
+    }
+}
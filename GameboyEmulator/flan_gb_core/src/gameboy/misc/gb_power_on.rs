@@ -0,0 +1,91 @@
+use std::str::FromStr;
+
+// SplitMix64. It's tiny and gives the same numbers on every machine and every version, so a
+// power-on seed in a movie file keeps meaning the same memory contents.
+struct PowerOnRng {
+    state: u64,
+}
+
+impl PowerOnRng {
+    // Each memory gets its own stream, so what one of them holds doesn't depend on the others
+    fn new(seed: u64, stream: u64) -> PowerOnRng {
+        PowerOnRng {
+            state: seed ^ stream.wrapping_mul(0x9E3779B97F4A7C15),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94D049BB133111EB);
+        value ^ (value >> 31)
+    }
+
+    fn fill(&mut self, memory: &mut [u8]) {
+        for chunk in memory.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// What RAM holds when the Game Boy powers on, before the game writes anything to it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MemoryInit {
+    /// Every byte set to the same value
+    Fill(u8),
+    /// Random bytes picked by the power-on seed
+    #[default]
+    Random,
+    /// Random bytes picked by the power-on seed, leaning towards 0x00 or 0xFF in blocks the way
+    /// a real DMG tends to power on
+    DmgPattern,
+    /// Random like `Random`, but every read of a byte that was never written is logged with the
+    /// PC and bank it came from, to catch uninitialized variables
+    Poison,
+}
+
+impl MemoryInit {
+    // Fills one memory, `address` being where it starts on the bus so each memory gets its own stream
+    pub(in super::super) fn fill(self, memory: &mut [u8], seed: u64, address: u16) {
+        let mut rng = PowerOnRng::new(seed, address as u64);
+        match self {
+            MemoryInit::Fill(value) => memory.fill(value),
+            MemoryInit::Random | MemoryInit::Poison => rng.fill(memory),
+            MemoryInit::DmgPattern => {
+                // WRAM and VRAM alternate between blocks of mostly set and mostly cleared bits,
+                // OAM does the same every other pair of bytes. The rest is just random.
+                let block_size = match address {
+                    0x8000 | 0xC000 => 0x100,
+                    0xFE00 => 2,
+                    _ => return rng.fill(memory),
+                };
+                for (i, byte) in memory.iter_mut().enumerate() {
+                    let (value, mask) = (rng.next_u64() as u8, rng.next_u64() as u8);
+                    *byte = match (i / block_size) % 2 == 1 {
+                        true => value & mask,
+                        false => value | mask,
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for MemoryInit {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_ascii_lowercase().as_str() {
+            "zeros" => Ok(MemoryInit::Fill(0x00)),
+            "ff" => Ok(MemoryInit::Fill(0xFF)),
+            "random" => Ok(MemoryInit::Random),
+            "dmg" => Ok(MemoryInit::DmgPattern),
+            "poison" => Ok(MemoryInit::Poison),
+            _ => Err(format!(
+                "Unknown memory init mode \"{text}\", use zeros, ff, random, dmg or poison"
+            )),
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::path::{Path, PathBuf};
+
+use super::gb_png::write_png;
+use super::gb_serial::SerialDevice;
+
+const PRINTER_MAGIC: [u8; 2] = [0x88, 0x33];
+const PRINTER_ID: u8 = 0x81;
+
+// Commands
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+// Status bits
+const STATUS_CHECKSUM_ERROR: u8 = 1 << 0;
+const STATUS_PRINTING: u8 = 1 << 1;
+const STATUS_IMAGE_FULL: u8 = 1 << 2;
+const STATUS_UNPROCESSED_DATA: u8 = 1 << 3;
+
+// The printer holds up to 8 KiB of tile data, which is a whole 160x144 screen
+const PRINTER_MEMORY_SIZE: usize = 0x2000;
+const PRINTER_WIDTH: usize = 160;
+const PRINTER_TILES_PER_ROW: usize = PRINTER_WIDTH / 8;
+
+// Each unit of margin feeds this many blank pixel rows
+const MARGIN_ROWS: usize = 8;
+
+// Where we are in the packet: magic, command, compression, length, data, checksum, then the 2 bytes we answer
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Id,
+    Status,
+}
+
+/// A Game Boy Printer. Every page it prints is saved as a PNG next to the ROM, as `rom_name.printN.png`.
+pub struct Printer {
+    output_base: PathBuf,
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    status: u8,
+    tile_data: Vec<u8>,
+    page: Vec<u8>,
+}
+
+impl Printer {
+    pub fn new(rom_path: impl AsRef<Path>) -> Printer {
+        Printer {
+            output_base: rom_path.as_ref().to_path_buf(),
+            state: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            status: 0,
+            tile_data: Vec::new(),
+            page: Vec::new(),
+        }
+    }
+
+    /// Saves whatever has been printed since the last page was finished
+    pub fn finish_page(&mut self) {
+        if self.page.is_empty() {
+            return;
+        }
+        let pixels = std::mem::take(&mut self.page);
+
+        // Don't overwrite earlier prints
+        let mut number = 1;
+        let path = loop {
+            let path = self
+                .output_base
+                .with_extension(format!("print{number}.png"));
+            if !path.exists() {
+                break path;
+            }
+            number += 1;
+        };
+
+        let height = pixels.len() / PRINTER_WIDTH;
+        match write_png(
+            &path,
+            PRINTER_WIDTH,
+            height,
+            png::ColorType::Grayscale,
+            &pixels,
+        ) {
+            Ok(()) => println!("Printed page to \"{}\"", path.display()),
+            Err(e) => println!("Unable to save printed page \"{}\": {e}", path.display()),
+        }
+    }
+
+    fn handle_packet(&mut self) {
+        // Packets with a bad checksum are thrown away
+        let expected = self.data.iter().fold(
+            (self.command as u16)
+                .wrapping_add(self.compressed as u16)
+                .wrapping_add(self.length & 0xFF)
+                .wrapping_add(self.length >> 8),
+            |sum, byte| sum.wrapping_add(*byte as u16),
+        );
+        if expected != self.checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        let mut printed = false;
+        match self.command {
+            COMMAND_INIT => {
+                self.tile_data.clear();
+                self.status = 0;
+            }
+            COMMAND_DATA => {
+                let data = std::mem::take(&mut self.data);
+                match self.compressed {
+                    true => decompress(&data, &mut self.tile_data),
+                    false => self.tile_data.extend_from_slice(&data),
+                }
+                self.tile_data.truncate(PRINTER_MEMORY_SIZE);
+            }
+            COMMAND_PRINT if self.data.len() >= 4 => {
+                let margins = self.data[1];
+                let palette = match self.data[2] {
+                    0x00 => 0xE4, // Some games leave it at 0, which prints like the default palette
+                    palette => palette,
+                };
+                self.print(margins >> 4, margins & 0x0F, palette);
+                printed = true;
+            }
+            COMMAND_STATUS => (),
+            _ => (),
+        }
+
+        self.status &= !(STATUS_PRINTING | STATUS_UNPROCESSED_DATA | STATUS_IMAGE_FULL);
+        if !self.tile_data.is_empty() {
+            self.status |= STATUS_UNPROCESSED_DATA;
+        }
+        if self.tile_data.len() >= PRINTER_MEMORY_SIZE {
+            self.status |= STATUS_IMAGE_FULL;
+        }
+
+        // Pretend printing takes a moment, games wait to see the printing bit once before moving on
+        if printed {
+            self.status |= STATUS_PRINTING;
+        }
+    }
+
+    fn print(&mut self, margin_before: u8, margin_after: u8, palette: u8) {
+        let tile_data = std::mem::take(&mut self.tile_data);
+        let blank_rows = |page: &mut Vec<u8>, units: u8| {
+            page.resize(
+                page.len() + units as usize * MARGIN_ROWS * PRINTER_WIDTH,
+                0xFF,
+            );
+        };
+        blank_rows(&mut self.page, margin_before);
+
+        // The tiles come in rows of 20, 16 bytes each
+        let tile_rows = tile_data.len() / (PRINTER_TILES_PER_ROW * 16);
+        for tile_row in 0..tile_rows {
+            for pixel_y in 0..8 {
+                for pixel_x in 0..PRINTER_WIDTH {
+                    let tile = tile_row * PRINTER_TILES_PER_ROW + pixel_x / 8;
+                    let low = tile_data[tile * 16 + pixel_y * 2];
+                    let high = tile_data[tile * 16 + pixel_y * 2 + 1];
+                    let bit = 7 - (pixel_x % 8);
+                    let color = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+                    let shade = (palette >> (color * 2)) & 0x03;
+                    self.page.push(0xFF - shade * 0x55);
+                }
+            }
+        }
+
+        // Feeding paper afterwards means the page is done
+        blank_rows(&mut self.page, margin_after);
+        if margin_after > 0 {
+            self.finish_page();
+        }
+    }
+}
+
+impl SerialDevice for Printer {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut response = 0x00;
+        self.state = match self.state {
+            PacketState::Magic(index) => match byte == PRINTER_MAGIC[index] {
+                true if index == 0 => PacketState::Magic(1),
+                true => PacketState::Command,
+                false if byte == PRINTER_MAGIC[0] => PacketState::Magic(1),
+                false => PacketState::Magic(0),
+            },
+            PacketState::Command => {
+                self.command = byte;
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.compressed = byte & 0x01 > 0;
+                PacketState::LengthLow
+            }
+            PacketState::LengthLow => {
+                self.length = byte as u16;
+                PacketState::LengthHigh
+            }
+            PacketState::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.data.clear();
+                match self.length {
+                    0 => PacketState::ChecksumLow,
+                    _ => PacketState::Data,
+                }
+            }
+            PacketState::Data => {
+                self.data.push(byte);
+                match self.data.len() == self.length as usize {
+                    true => PacketState::ChecksumLow,
+                    false => PacketState::Data,
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.checksum = byte as u16;
+                PacketState::ChecksumHigh
+            }
+            PacketState::ChecksumHigh => {
+                self.checksum |= (byte as u16) << 8;
+                self.handle_packet();
+                PacketState::Id
+            }
+            PacketState::Id => {
+                response = PRINTER_ID;
+                PacketState::Status
+            }
+            PacketState::Status => {
+                response = self.status;
+                PacketState::Magic(0)
+            }
+        };
+        response
+    }
+}
+
+impl Drop for Printer {
+    fn drop(&mut self) {
+        self.finish_page();
+    }
+}
+
+// Runs are stored as a length byte: with bit 7 set, the next byte repeats (length & 0x7F) + 2 times,
+// otherwise the next length + 1 bytes are copied as is
+fn decompress(data: &[u8], output: &mut Vec<u8>) {
+    let mut index = 0;
+    while index < data.len() {
+        let length = data[index];
+        index += 1;
+        if length & 0x80 > 0 {
+            let Some(value) = data.get(index) else {
+                break;
+            };
+            output.extend(std::iter::repeat_n(*value, (length & 0x7F) as usize + 2));
+            index += 1;
+        } else {
+            let end = (index + length as usize + 1).min(data.len());
+            output.extend_from_slice(&data[index..end]);
+            index = end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sends a whole packet and returns the status byte the printer answers with
+    fn send_packet(
+        printer: &mut Printer,
+        command: u8,
+        compressed: bool,
+        data: &[u8],
+        checksum: u16,
+    ) -> u8 {
+        let length = data.len() as u16;
+        let mut bytes = vec![
+            PRINTER_MAGIC[0],
+            PRINTER_MAGIC[1],
+            command,
+            compressed as u8,
+            length as u8,
+            (length >> 8) as u8,
+        ];
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        for byte in bytes {
+            assert_eq!(printer.transfer(byte), 0x00);
+        }
+        assert_eq!(printer.transfer(0x00), PRINTER_ID);
+        printer.transfer(0x00)
+    }
+
+    // Sum of every byte after the magic, kept to 16 bits
+    fn checksum(command: u8, compressed: bool, data: &[u8]) -> u16 {
+        let length = data.len() as u32;
+        let sum = data.iter().fold(
+            command as u32 + compressed as u32 + (length & 0xFF) + (length >> 8),
+            |sum, byte| sum + *byte as u32,
+        );
+        sum as u16
+    }
+
+    #[test]
+    fn decompress_runs_and_literals() {
+        let mut output = Vec::new();
+        decompress(&[0x81, 0xAA, 0x02, 1, 2, 3, 0xFF, 0x00], &mut output);
+        let mut expected = vec![0xAA; 3];
+        expected.extend_from_slice(&[1, 2, 3]);
+        expected.extend_from_slice(&[0x00; 0x7F + 2]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn decompress_cut_off_data() {
+        // Whatever is there gets used, a run without its value is dropped
+        let mut output = Vec::new();
+        decompress(&[0x05, 1, 2], &mut output);
+        assert_eq!(output, [1, 2]);
+        output.clear();
+        decompress(&[0x04, 9, 0x83], &mut output);
+        assert_eq!(output, [9, 0x83]);
+        output.clear();
+        decompress(&[0x83], &mut output);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn checksums() {
+        let mut printer = Printer::new("test.gb");
+        assert_eq!(
+            send_packet(
+                &mut printer,
+                COMMAND_INIT,
+                false,
+                &[],
+                checksum(COMMAND_INIT, false, &[])
+            ),
+            0
+        );
+
+        // A bad checksum throws the data away
+        let data = [0x12; 0x280];
+        let status = send_packet(&mut printer, COMMAND_DATA, false, &data, 0x1234);
+        assert_eq!(status, STATUS_CHECKSUM_ERROR);
+        assert!(printer.tile_data.is_empty());
+
+        // This one adds up to more than 16 bits
+        let data = [0xFF; 0x280];
+        let sum = checksum(COMMAND_DATA, false, &data);
+        let status = send_packet(&mut printer, COMMAND_DATA, false, &data, sum);
+        assert_eq!(status, STATUS_UNPROCESSED_DATA);
+        assert_eq!(printer.tile_data, data);
+    }
+
+    #[test]
+    fn compressed_data_packet() {
+        let mut printer = Printer::new("test.gb");
+        let data = [0x80 | 0x7E, 0x55, 0x01, 0x11, 0x22];
+        let sum = checksum(COMMAND_DATA, true, &data);
+        assert_eq!(
+            send_packet(&mut printer, COMMAND_DATA, true, &data, sum),
+            STATUS_UNPROCESSED_DATA
+        );
+        let mut expected = vec![0x55; 0x80];
+        expected.extend_from_slice(&[0x11, 0x22]);
+        assert_eq!(printer.tile_data, expected);
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::Path;
+
+use super::super::GameBoy;
+use super::gb_png::{read_png_rgb, write_png, ImageError};
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// The result of comparing the screen against a reference image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageComparison {
+    /// How many pixels differ by more than the tolerance
+    pub differing_pixels: usize,
+    /// The biggest difference in any colour channel, over all pixels
+    pub max_difference: u8,
+}
+
+impl ImageComparison {
+    pub fn matches(&self) -> bool {
+        self.differing_pixels == 0
+    }
+}
+
+impl GameBoy {
+    /// Saves the screen as a PNG, with every pixel scaled up to a `scale` by `scale` square
+    pub fn save_screenshot(&self, path: impl AsRef<Path>, scale: usize) -> Result<(), ImageError> {
+        let scale = scale.max(1);
+        let (width, height) = (SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale);
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = self.framebuffer[(x / scale) + (y / scale) * SCREEN_WIDTH];
+                pixels.extend([(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+            }
+        }
+        write_png(path.as_ref(), width, height, png::ColorType::Rgb, &pixels)
+    }
+
+    /// Compares the screen against a reference PNG, which can be at any integer scale.
+    /// Pixels count as different when any colour channel is off by more than `tolerance`.
+    /// If `diff_path` is given, an image is written there with the differing pixels in red.
+    pub fn compare_screenshot(
+        &self,
+        reference_path: impl AsRef<Path>,
+        tolerance: u8,
+        diff_path: Option<&Path>,
+    ) -> Result<ImageComparison, ImageError> {
+        let (width, height, reference) = read_png_rgb(reference_path.as_ref())?;
+        let scale = width / SCREEN_WIDTH;
+        if scale == 0 || width != SCREEN_WIDTH * scale || height != SCREEN_HEIGHT * scale {
+            return Err(ImageError::SizeMismatch {
+                expected: (SCREEN_WIDTH, SCREEN_HEIGHT),
+                found: (width, height),
+            });
+        }
+
+        let mut comparison = ImageComparison {
+            differing_pixels: 0,
+            max_difference: 0,
+        };
+        let mut diff = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                // Take the top left pixel of every scaled up square in the reference
+                let index = (x * scale + y * scale * width) * 3;
+                let wanted = &reference[index..index + 3];
+                let pixel = self.framebuffer[x + y * SCREEN_WIDTH];
+                let got = [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8];
+
+                let difference = (0..3)
+                    .map(|i| got[i].abs_diff(wanted[i]))
+                    .max()
+                    .unwrap_or(0);
+                comparison.max_difference = comparison.max_difference.max(difference);
+                if difference > tolerance {
+                    comparison.differing_pixels += 1;
+                    diff.extend([0xFF, 0x00, 0x00]);
+                } else {
+                    // Faded out, so the red stands out against what was on screen
+                    let gray = ((got[0] as u16 + got[1] as u16 + got[2] as u16) / 3) as u8;
+                    let faded = 0xC0 + gray / 4;
+                    diff.extend([faded, faded, faded]);
+                }
+            }
+        }
+
+        if let Some(diff_path) = diff_path {
+            write_png(
+                diff_path,
+                SCREEN_WIDTH,
+                SCREEN_HEIGHT,
+                png::ColorType::Rgb,
+                &diff,
+            )?;
+        }
+        Ok(comparison)
+    }
+}
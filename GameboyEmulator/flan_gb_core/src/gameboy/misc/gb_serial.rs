@@ -0,0 +1,141 @@
+use super::super::cpu::gb_interrupts::InterruptMasks;
+use super::super::GameBoy;
+
+// The internal clock runs at 8192 Hz, which is 256 steps of 2 dots per bit. CGB games can pick 262144 Hz
+// instead. In double speed mode both the clock and the steps go twice as fast, so the counts stay the same.
+const SERIAL_STEPS_PER_BIT: u32 = 256;
+const SERIAL_FAST_STEPS_PER_BIT: u32 = 8;
+
+/// Something plugged into the link port, like another Game Boy or a printer
+pub trait SerialDevice {
+    /// We clocked a transfer ourselves. `byte` is shifted out, and whatever the device shifts back is returned.
+    fn transfer(&mut self, byte: u8) -> u8;
+
+    /// We're waiting for the device to clock a transfer, and will send `byte` when it does.
+    /// `None` means we stopped waiting.
+    fn set_waiting_byte(&mut self, _byte: Option<u8>) {}
+
+    /// The byte the device clocked in to us, if it did since the last call
+    fn poll_received(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+impl GameBoy {
+    /// Plugs a device into the link port, replacing whatever was there
+    pub fn connect_serial(&mut self, device: Box<dyn SerialDevice>) {
+        self.serial_device = Some(device);
+        self.update_serial_waiting();
+    }
+
+    pub fn disconnect_serial(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.serial_device.take()
+    }
+
+    pub(in super::super) fn write_serial_data(&mut self, value: u8) {
+        self.io[0x01] = value;
+        self.update_serial_waiting();
+    }
+
+    pub(in super::super) fn write_serial_control(&mut self, value: u8) {
+        // Unused bits read back as 1, CGB also has the clock speed in bit 1
+        self.io[0x02] = match self.cgb_mode {
+            true => value | 0x7C,
+            false => value | 0x7E,
+        };
+
+        // Start a transfer if we're the one providing the clock
+        self.serial_cycles_left = 0;
+        if value & 0x81 == 0x81 {
+            let steps_per_bit = match self.cgb_mode && value & 0x02 > 0 {
+                true => SERIAL_FAST_STEPS_PER_BIT,
+                false => SERIAL_STEPS_PER_BIT,
+            };
+            self.serial_cycles_left = steps_per_bit * 8;
+
+            // Keep track of what was sent even if the game restarts the transfer before it's done
+            if let Some(output) = &mut self.serial_output {
+                output.push(self.io[0x01]);
+            }
+        }
+        self.update_serial_waiting();
+    }
+
+    // Should be called every step
+    pub(in super::super) fn run_serial_cycle(&mut self) {
+        // Transfer clocked by us, the whole byte gets exchanged at the end
+        if self.serial_cycles_left > 0 {
+            self.serial_cycles_left -= 1;
+            if self.serial_cycles_left == 0 {
+                let byte_in = match &mut self.serial_device {
+                    Some(device) => device.transfer(self.io[0x01]),
+                    None => 0xFF, // Nothing on the other end, the line stays high
+                };
+                self.finish_serial_transfer(byte_in);
+            }
+            return;
+        }
+
+        // Transfer clocked by the other side. No need to check the device every single cycle for that.
+        if self.io[0x02] & 0x81 != 0x80 {
+            return;
+        }
+        self.serial_poll_timer += 1;
+        if self.serial_poll_timer < SERIAL_STEPS_PER_BIT {
+            return;
+        }
+        self.serial_poll_timer = 0;
+        if let Some(byte_in) = self
+            .serial_device
+            .as_mut()
+            .and_then(|device| device.poll_received())
+        {
+            if let Some(output) = &mut self.serial_output {
+                output.push(self.io[0x01]);
+            }
+            self.finish_serial_transfer(byte_in);
+        }
+    }
+
+    fn finish_serial_transfer(&mut self, byte_in: u8) {
+        self.io[0x01] = byte_in;
+        self.io[0x02] &= 0x7F;
+        self.io[0x0F] |= InterruptMasks::Serial as u8;
+        self.update_serial_waiting();
+    }
+
+    // Let the device know what we'd send if it started a transfer
+    fn update_serial_waiting(&mut self) {
+        let waiting = self.io[0x02] & 0x81 == 0x80;
+        let byte = waiting.then_some(self.io[0x01]);
+        if let Some(device) = &mut self.serial_device {
+            device.set_waiting_byte(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_is_only_kept_while_capturing() {
+        let mut game_boy = GameBoy::with_power_on_seed(1);
+        game_boy.write_serial_data(b'A');
+        game_boy.write_serial_control(0x81);
+        assert!(game_boy.take_serial_output().is_empty());
+
+        game_boy.set_serial_capture(true);
+        game_boy.write_serial_data(b'B');
+        game_boy.write_serial_control(0x81);
+        game_boy.write_serial_data(b'C');
+        game_boy.write_serial_control(0x81);
+        assert_eq!(game_boy.take_serial_output(), b"BC");
+        assert!(game_boy.take_serial_output().is_empty());
+
+        game_boy.write_serial_data(b'D');
+        game_boy.write_serial_control(0x81);
+        game_boy.set_serial_capture(false);
+        assert!(game_boy.take_serial_output().is_empty());
+    }
+}
@@ -0,0 +1,12 @@
+pub mod gb_cartridge;
+pub mod gb_hdma;
+pub mod gb_input;
+pub mod gb_io_registers;
+pub mod gb_link_cable;
+pub mod gb_misc;
+pub mod gb_png;
+pub mod gb_poison;
+pub mod gb_power_on;
+pub mod gb_printer;
+pub mod gb_screenshot;
+pub mod gb_serial;
@@ -11,7 +11,7 @@ enum LcdInterruptMasks {
 }
 
 impl GameBoy {
-    pub(crate) fn run_ppu_cycle(&mut self) {
+    pub(in super::super) fn run_ppu_cycle(&mut self) {
         let ppu_y_prev = self.ppu_ly;
         if self.io[0x40] & 0x80 == 0 {
             self.ppu_dots_into_curr_line = 0;
@@ -53,6 +53,7 @@ impl GameBoy {
                                 posx: self.oam[sprite_base_address + 1],
                                 tile: self.oam[sprite_base_address + 2],
                                 attr: self.oam[sprite_base_address + 3],
+                                index: (sprite_base_address / 4) as u8,
                             });
                             if self.ppu_sprite_buffer.len() == 10 {
                                 break;
@@ -122,31 +123,53 @@ impl GameBoy {
                     // Get tile index from memory
                     let tile_index = self.vram[tile_index_sample_address & 0x1FFF];
 
+                    // On CGB, the tile attributes are in VRAM bank 1 at the same address
+                    let tile_attributes = match self.cgb_mode {
+                        true => self.vram[0x2000 + (tile_index_sample_address & 0x1FFF)],
+                        false => 0,
+                    };
+
+                    // Handle vertical flipping
+                    let mut tile_row = self.ppu_tilemap_y as usize & 0x07;
+                    if tile_attributes & 0x40 > 0 {
+                        tile_row = 7 - tile_row;
+                    }
+
                     // Create tile data index
                     let mut tile_data_sample_address: usize = 0x8000;
                     tile_data_sample_address += (tile_index as usize) << 4;
-                    tile_data_sample_address += (self.ppu_tilemap_y as usize & 0x07) * 2;
+                    tile_data_sample_address += tile_row * 2;
 
                     if (tile_data_sample_address < 0x8800) && (self.io[0x40] & (1 << 4) == 0) {
                         tile_data_sample_address += 0x1000;
                     }
 
-                    // Load 2 bytes (1 rows of pixels)
-                    let row_low = self.vram[tile_data_sample_address & 0x1FFF];
-                    let row_high = self.vram[(tile_data_sample_address + 1) & 0x1FFF];
+                    // Load 2 bytes (1 rows of pixels), from VRAM bank 1 if the attributes say so
+                    let bank_offset = ((tile_attributes as usize & 0x08) >> 3) * 0x2000;
+                    let row_low = self.vram[bank_offset + (tile_data_sample_address & 0x1FFF)];
+                    let row_high =
+                        self.vram[bank_offset + ((tile_data_sample_address + 1) & 0x1FFF)];
 
                     // Parse them into color indices
                     for x in 0..8 {
+                        // Handle horizontal flipping
+                        let bit = match tile_attributes & 0x20 {
+                            0 => 7 - x,
+                            _ => x,
+                        };
                         let mut pixel = 0;
-                        if row_low & (1 << (7 - x)) > 0 {
+                        if row_low & (1 << bit) > 0 {
                             pixel += 1;
                         }
-                        if row_high & (1 << (7 - x)) > 0 {
+                        if row_high & (1 << bit) > 0 {
                             pixel += 2;
                         }
                         self.ppu_fifo.push_back(PpuFifoElement {
                             color: pixel,
                             source: 0,
+                            palette: tile_attributes & 0x07,
+                            priority: tile_attributes & 0x80 > 0,
+                            oam_index: 0,
                         });
                     }
                     self.ppu_tilemap_x = self.ppu_tilemap_x.wrapping_add(8);
@@ -172,9 +195,15 @@ impl GameBoy {
                                 tile_data_sample_address += (sprite_tile_y as usize) * 2;
                             }
 
-                            // Load the row of pixels
-                            let row_low = self.vram[tile_data_sample_address & 0x1FFF];
-                            let row_high = self.vram[(tile_data_sample_address + 1) & 0x1FFF];
+                            // Load the row of pixels, on CGB sprites can use VRAM bank 1 too
+                            let bank_offset = match self.cgb_mode {
+                                true => ((sprite.attr as usize & 0x08) >> 3) * 0x2000,
+                                false => 0,
+                            };
+                            let row_low =
+                                self.vram[bank_offset + (tile_data_sample_address & 0x1FFF)];
+                            let row_high =
+                                self.vram[bank_offset + ((tile_data_sample_address + 1) & 0x1FFF)];
 
                             // Mix it into the queue
                             for x in 0..8 {
@@ -192,6 +221,9 @@ impl GameBoy {
                                 let new_fifo_element = PpuFifoElement {
                                     color: color_sprite,
                                     source: source_sprite,
+                                    palette: sprite.attr & 0x07,
+                                    priority: false,
+                                    oam_index: sprite.index,
                                 };
 
                                 // Handle flipping
@@ -209,12 +241,29 @@ impl GameBoy {
                                     continue;
                                 }
 
+                                // If another sprite already drew here, on DMG the one with the lower X wins, which is the one
+                                // that got mixed in first. On CGB the lower OAM index wins no matter the X coordinate.
+                                let background = &self.ppu_fifo[fifo_index as usize];
+                                if background.source != 0 && background.color != 0 {
+                                    if self.cgb_mode && sprite.index < background.oam_index {
+                                        self.ppu_fifo[fifo_index as usize] = new_fifo_element;
+                                    }
+                                    continue;
+                                }
+
+                                // The background wins if it's not color 0 and either the sprite or (on CGB) the tile asks for it
+                                let mut background_has_priority = background.source == 0
+                                    && background.color != 0
+                                    && (sprite.attr & 0x80 > 0
+                                        || (self.cgb_mode && background.priority));
+
+                                // On CGB, clearing LCDC bit 0 puts sprites on top no matter what
+                                if self.cgb_mode && (self.io[0x40] & 0x01) == 0 {
+                                    background_has_priority = false;
+                                }
+
                                 // Replace the tilemap fifo element if the color isn't 0
-                                if (new_fifo_element.color != 0)
-                                    && !((sprite.attr & 0x80 > 0)
-                                        && (self.ppu_fifo[fifo_index as usize].source == 0
-                                            && self.ppu_fifo[fifo_index as usize].color != 0))
-                                {
+                                if (new_fifo_element.color != 0) && !background_has_priority {
                                     self.ppu_fifo[fifo_index as usize] = new_fifo_element;
                                 }
                             }
@@ -229,15 +278,27 @@ impl GameBoy {
                         self.ppu_fifo.pop_front();
                     } else {
                         let curr_pixel_index = self.ppu_fifo.pop_front().unwrap();
-                        let curr_pixel_color = self.io[0x47 + curr_pixel_index.source as usize]
-                            >> (curr_pixel_index.color * 2)
-                            & 0x03;
-                        let final_color: u32 =
-                            (((curr_pixel_color ^ 0b11) as u32) * (235 / 3)) * 0x00010101;
+                        let final_color = if self.cgb_mode {
+                            // Look up the 15-bit color in palette RAM, 8 bytes per palette and 2 bytes per color
+                            let palette_ram = match curr_pixel_index.source {
+                                0 => &self.bg_palette_ram,
+                                _ => &self.obj_palette_ram,
+                            };
+                            let index = (curr_pixel_index.palette as usize) * 8
+                                + (curr_pixel_index.color as usize) * 2;
+                            rgb555_to_argb(
+                                palette_ram[index] as u16 | (palette_ram[index + 1] as u16) << 8,
+                            )
+                        } else {
+                            let curr_pixel_color = self.io[0x47 + curr_pixel_index.source as usize]
+                                >> (curr_pixel_index.color * 2)
+                                & 0x03;
+                            (((curr_pixel_color ^ 0b11) as u32) * (235 / 3)) * 0x00010101
+                        };
                         if (self.io[0x40] & 0x80) == 0 {
                             self.framebuffer[self.ppu_lx as usize + self.ppu_ly as usize * 160] =
                                 0xFFFFFFFF; // emulate the extra white from the screen being off
-                        } else if (self.io[0x40] & 0x01) == 0 {
+                        } else if (self.io[0x40] & 0x01) == 0 && !self.cgb_mode {
                             self.framebuffer[self.ppu_lx as usize + self.ppu_ly as usize * 160] =
                                 0xFFEAEAEA;
                         } else {
@@ -258,6 +319,11 @@ impl GameBoy {
                     if (self.io[0x41] & LcdInterruptMasks::Hblank as u8) > 0 {
                         self.io[0x0F] |= InterruptMasks::Lcd as u8;
                     }
+
+                    // H-blank DMA copies one block every H-blank
+                    if self.hdma_active {
+                        self.copy_hdma_block();
+                    }
                 }
             }
             0 => {
@@ -323,3 +389,57 @@ impl GameBoy {
         //print!("PPU STATS: mode: {:>3}, curr_dots_mode: {:>3}, curr_dots_line {:>3}, lx: {:>3}, ly: {:>3}, tilemap_x: {:>3}, tilemap_y: {:>3}    \r", self.ppu_mode, self.ppu_dots_into_curr_mode, self.ppu_dots_into_curr_line, self.ppu_lx, self.ppu_ly, self.ppu_tilemap_x, self.ppu_tilemap_y);
     }
 }
+
+// Convert a CGB color (%0bbbbbgggggrrrrr) to 0xAARRGGBB
+fn rgb555_to_argb(color: u16) -> u32 {
+    let expand = |channel: u16| -> u32 {
+        let channel = (channel & 0x1F) as u32;
+        (channel << 3) | (channel >> 2)
+    };
+    0xFF000000 | expand(color) << 16 | expand(color >> 5) << 8 | expand(color >> 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A cartridge that turns on the LCD with sprites, and two solid sprites where the one at the lower X
+    // has the higher OAM index. They overlap at x = 12..=15 on the first 8 lines.
+    fn overlapping_sprites(cgb: bool) -> GameBoy {
+        let mut rom = vec![0x00; 0x8000];
+        // LD A, $93; LDH [$FF40], A; JR -2
+        rom[0x100..0x106].copy_from_slice(&[0x3E, 0x93, 0xE0, 0x40, 0x18, 0xFE]);
+        rom[0x143] = if cgb { 0x80 } else { 0x00 };
+        let mut game_boy = GameBoy::with_power_on_seed(1);
+        assert!(game_boy.load_rom(rom));
+        game_boy.skip_boot_rom();
+
+        game_boy.vram.fill(0x00);
+        game_boy.vram[0x0010..0x0020].fill(0xFF);
+        game_boy.oam[0..8].copy_from_slice(&[16, 20, 1, 0x00, 16, 16, 1, 0x11]);
+        game_boy.io[0x48] = 0xFF;
+        game_boy.io[0x49] = 0x00;
+        game_boy.obj_palette_ram[6..8].copy_from_slice(&0x001Fu16.to_le_bytes());
+        game_boy.obj_palette_ram[14..16].copy_from_slice(&0x7C00u16.to_le_bytes());
+        game_boy.run_frame();
+        game_boy.run_frame();
+        game_boy
+    }
+
+    #[test]
+    fn dmg_sprites_with_lower_x_win() {
+        let game_boy = overlapping_sprites(false);
+        let line = &game_boy.framebuffer[2 * 160..3 * 160];
+        assert_ne!(line[10], line[17]);
+        assert_eq!(line[13], line[10]);
+    }
+
+    #[test]
+    fn cgb_sprites_with_lower_oam_index_win() {
+        let game_boy = overlapping_sprites(true);
+        let line = &game_boy.framebuffer[2 * 160..3 * 160];
+        assert_eq!(line[10], rgb555_to_argb(0x7C00));
+        assert_eq!(line[17], rgb555_to_argb(0x001F));
+        assert_eq!(line[13], rgb555_to_argb(0x001F));
+    }
+}
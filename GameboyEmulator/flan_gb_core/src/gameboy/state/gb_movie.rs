@@ -0,0 +1,400 @@
+use std::fmt;
+
+use super::super::misc::gb_misc::rom_hash;
+use super::super::{GameBoy, InputState, MemoryInit};
+use super::gb_state_io::{SaveStateError, StateReader, StateWriter};
+
+const MOVIE_MAGIC: &[u8; 8] = b"FLANGBMV";
+const MOVIE_VERSION: u32 = 2;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MovieError {
+    NotAMovie,
+    UnsupportedVersion(u32),
+    WrongRom,
+    Corrupted,
+    /// The screen didn't come out the way it did when the movie was recorded
+    Desync {
+        frame: usize,
+    },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::NotAMovie => write!(f, "this file is not a movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "movie version {version} is not supported")
+            }
+            MovieError::WrongRom => write!(f, "this movie was recorded with a different ROM"),
+            MovieError::Corrupted => write!(f, "this movie is corrupted"),
+            MovieError::Desync { frame } => write!(f, "the movie desynced on frame {frame}"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<SaveStateError> for MovieError {
+    fn from(_: SaveStateError) -> Self {
+        MovieError::Corrupted
+    }
+}
+
+// What was held during a frame, and a hash of the screen it drew
+struct MovieFrame {
+    input: u8,
+    framebuffer_hash: u64,
+}
+
+/// The buttons held on every frame since power-on, so a run can be played back exactly.
+///
+/// A movie remembers the ROM, power-on seed and memory init it started from, and a hash of the screen
+/// after every frame, so playback can tell exactly where it went differently. Save data, the
+/// serial port and the real-time clock aren't part of it, so movies start without a save file.
+pub struct Movie {
+    /// Who recorded the movie
+    pub author: String,
+    /// Anything else worth knowing, like what the movie shows off
+    pub description: String,
+    rom_hash: u64,
+    power_on_seed: u64,
+    memory_init: MemoryInit,
+    skip_boot: bool,
+    rerecords: u32,
+    frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    /// Starts a movie from the way `game_boy` powered on. Call it with the cartridge in, before
+    /// the first frame, and after `skip_boot_rom` if the boot ROM should be skipped.
+    pub fn new(game_boy: &GameBoy) -> Movie {
+        Movie {
+            author: String::new(),
+            description: String::new(),
+            rom_hash: game_boy.rom_hash,
+            power_on_seed: game_boy.power_on_seed,
+            memory_init: game_boy.memory_init,
+            skip_boot: !game_boy.rom_chip_enabled,
+            rerecords: 0,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Powers on a Game Boy the same way as when the movie was recorded, ready for the first frame
+    pub fn power_on(&self, rom: Vec<u8>) -> Result<GameBoy, MovieError> {
+        let mut game_boy = GameBoy::with_power_on_seed(self.power_on_seed);
+        game_boy.set_memory_init(self.memory_init);
+        if !game_boy.load_rom(rom) || game_boy.rom_hash != self.rom_hash {
+            return Err(MovieError::WrongRom);
+        }
+        if self.skip_boot {
+            game_boy.skip_boot_rom();
+        }
+        Ok(game_boy)
+    }
+
+    /// Should be called after every emulated frame, with the input it ran with
+    pub fn record_frame(&mut self, input: &InputState, game_boy: &GameBoy) {
+        self.frames.push(MovieFrame {
+            input: encode_input(input),
+            framebuffer_hash: framebuffer_hash(game_boy),
+        });
+    }
+
+    /// The input to run a frame with, or None once the movie is over
+    pub fn input(&self, frame: usize) -> Option<InputState> {
+        self.frames
+            .get(frame)
+            .map(|frame| decode_input(frame.input))
+    }
+
+    /// Checks the screen after running a frame against the recording
+    pub fn verify_frame(&self, frame: usize, game_boy: &GameBoy) -> Result<(), MovieError> {
+        match self.frames.get(frame) {
+            Some(recorded) if recorded.framebuffer_hash == framebuffer_hash(game_boy) => Ok(()),
+            _ => Err(MovieError::Desync { frame }),
+        }
+    }
+
+    /// Throws away everything from frame `frames` on, to record over it
+    pub fn truncate(&mut self, frames: usize) {
+        self.frames.truncate(frames);
+    }
+
+    /// Counts going back to record a part of the movie again
+    pub fn add_rerecord(&mut self) {
+        self.rerecords = self.rerecords.saturating_add(1);
+    }
+
+    /// Number of frames in the movie
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// How many times part of the movie was recorded again
+    pub fn rerecords(&self) -> u32 {
+        self.rerecords
+    }
+
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    pub fn power_on_seed(&self) -> u64 {
+        self.power_on_seed
+    }
+
+    pub fn memory_init(&self) -> MemoryInit {
+        self.memory_init
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut movie = StateWriter::new();
+
+        // Header
+        movie.bytes(MOVIE_MAGIC);
+        movie.u32(MOVIE_VERSION);
+        movie.u64(self.rom_hash);
+        movie.u64(self.power_on_seed);
+        let (kind, value) = encode_memory_init(self.memory_init);
+        movie.u8(kind);
+        movie.u8(value);
+        movie.bool(self.skip_boot);
+        movie.u32(self.rerecords);
+        movie.vec(self.author.as_bytes());
+        movie.vec(self.description.as_bytes());
+
+        // Frames
+        movie.usize(self.frames.len());
+        for frame in &self.frames {
+            movie.u8(frame.input);
+            movie.u64(frame.framebuffer_hash);
+        }
+        movie.data
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, MovieError> {
+        let mut reader = StateReader::new(bytes);
+
+        // Check the header
+        let mut magic = [0u8; 8];
+        reader
+            .bytes(&mut magic)
+            .map_err(|_| MovieError::NotAMovie)?;
+        if &magic != MOVIE_MAGIC {
+            return Err(MovieError::NotAMovie);
+        }
+        let version = reader.u32()?;
+        if !(1..=MOVIE_VERSION).contains(&version) {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let rom_hash = reader.u64()?;
+        let power_on_seed = reader.u64()?;
+        // Version 1 movies were always recorded with random memory
+        let memory_init = match version {
+            1 => MemoryInit::Random,
+            _ => decode_memory_init(reader.u8()?, reader.u8()?)?,
+        };
+        let mut movie = Movie {
+            rom_hash,
+            power_on_seed,
+            memory_init,
+            skip_boot: reader.bool()?,
+            rerecords: reader.u32()?,
+            author: String::from_utf8_lossy(&reader.vec()?).into_owned(),
+            description: String::from_utf8_lossy(&reader.vec()?).into_owned(),
+            frames: Vec::new(),
+        };
+        for _ in 0..reader.usize()? {
+            movie.frames.push(MovieFrame {
+                input: reader.u8()?,
+                framebuffer_hash: reader.u64()?,
+            });
+        }
+        match reader.is_empty() {
+            true => Ok(movie),
+            false => Err(MovieError::Corrupted),
+        }
+    }
+}
+
+fn framebuffer_hash(game_boy: &GameBoy) -> u64 {
+    let bytes: Vec<u8> = game_boy
+        .framebuffer
+        .iter()
+        .flat_map(|pixel| pixel.to_le_bytes())
+        .collect();
+    rom_hash(&bytes)
+}
+
+fn encode_memory_init(memory_init: MemoryInit) -> (u8, u8) {
+    match memory_init {
+        MemoryInit::Fill(value) => (0, value),
+        MemoryInit::Random => (1, 0),
+        MemoryInit::DmgPattern => (2, 0),
+        MemoryInit::Poison => (3, 0),
+    }
+}
+
+fn decode_memory_init(kind: u8, value: u8) -> Result<MemoryInit, MovieError> {
+    match kind {
+        0 => Ok(MemoryInit::Fill(value)),
+        1 => Ok(MemoryInit::Random),
+        2 => Ok(MemoryInit::DmgPattern),
+        3 => Ok(MemoryInit::Poison),
+        _ => Err(MovieError::Corrupted),
+    }
+}
+
+// One bit per button, set while it's held
+fn encode_input(input: &InputState) -> u8 {
+    [
+        input.a,
+        input.b,
+        input.select,
+        input.start,
+        input.right,
+        input.left,
+        input.up,
+        input.down,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (bit, &held)| bits | (held as u8) << bit)
+}
+
+fn decode_input(bits: u8) -> InputState {
+    let held = |bit: u8| bits & (1 << bit) != 0;
+    InputState {
+        a: held(0),
+        b: held(1),
+        select: held(2),
+        start: held(3),
+        right: held(4),
+        left: held(5),
+        up: held(6),
+        down: held(7),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_movie() -> Movie {
+        Movie {
+            author: "Flan".to_string(),
+            description: "Goes right, then jumps ✓".to_string(),
+            rom_hash: 0x0123_4567_89AB_CDEF,
+            power_on_seed: 42,
+            memory_init: MemoryInit::Fill(0x5A),
+            skip_boot: true,
+            rerecords: 7,
+            frames: (0..300)
+                .map(|frame| MovieFrame {
+                    input: frame as u8,
+                    framebuffer_hash: frame * 31,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let movie = test_movie();
+        let bytes = movie.to_bytes();
+        let loaded = Movie::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.author, movie.author);
+        assert_eq!(loaded.description, movie.description);
+        assert_eq!(loaded.rom_hash(), movie.rom_hash());
+        assert_eq!(loaded.power_on_seed(), movie.power_on_seed());
+        assert_eq!(loaded.memory_init(), movie.memory_init());
+        assert_eq!(loaded.skip_boot, movie.skip_boot);
+        assert_eq!(loaded.rerecords(), movie.rerecords());
+        assert_eq!(loaded.len(), movie.len());
+        for (loaded, frame) in loaded.frames.iter().zip(&movie.frames) {
+            assert_eq!(loaded.input, frame.input);
+            assert_eq!(loaded.framebuffer_hash, frame.framebuffer_hash);
+        }
+        assert!(loaded.to_bytes() == bytes);
+    }
+
+    #[test]
+    fn every_memory_init_round_trips() {
+        for memory_init in [
+            MemoryInit::Fill(0xFF),
+            MemoryInit::Random,
+            MemoryInit::DmgPattern,
+            MemoryInit::Poison,
+        ] {
+            let mut movie = test_movie();
+            movie.memory_init = memory_init;
+            let loaded = Movie::from_bytes(&movie.to_bytes()).unwrap();
+            assert_eq!(loaded.memory_init(), memory_init);
+        }
+    }
+
+    #[test]
+    fn version_1_has_random_memory() {
+        // Same as version 2, without the memory init after the seed
+        let mut bytes = test_movie().to_bytes();
+        bytes[8..12].copy_from_slice(&1u32.to_le_bytes());
+        bytes.drain(28..30);
+        let loaded = Movie::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.memory_init(), MemoryInit::Random);
+        assert_eq!(loaded.len(), 300);
+    }
+
+    #[test]
+    fn rejects_broken_movies() {
+        let bytes = test_movie().to_bytes();
+        assert_eq!(
+            Movie::from_bytes(b"FLANGBSS").err(),
+            Some(MovieError::NotAMovie)
+        );
+        assert_eq!(Movie::from_bytes(b"").err(), Some(MovieError::NotAMovie));
+
+        let mut newer = bytes.clone();
+        newer[8..12].copy_from_slice(&(MOVIE_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Movie::from_bytes(&newer).err(),
+            Some(MovieError::UnsupportedVersion(MOVIE_VERSION + 1))
+        );
+
+        assert_eq!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]).err(),
+            Some(MovieError::Corrupted)
+        );
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            Movie::from_bytes(&longer).err(),
+            Some(MovieError::Corrupted)
+        );
+        let mut bad_memory_init = bytes;
+        bad_memory_init[28] = 9;
+        assert_eq!(
+            Movie::from_bytes(&bad_memory_init).err(),
+            Some(MovieError::Corrupted)
+        );
+    }
+
+    #[test]
+    fn input_round_trips() {
+        for bits in 0..=0xFF {
+            assert_eq!(encode_input(&decode_input(bits)), bits);
+        }
+        let input = InputState {
+            a: true,
+            down: true,
+            ..Default::default()
+        };
+        assert_eq!(decode_input(encode_input(&input)), input);
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::VecDeque;
+
+use super::super::GameBoy;
+
+/// Keeps a history of snapshots of the machine, so the game can be played backwards.
+///
+/// Only the newest snapshot is kept in full. Every older snapshot is stored as the difference
+/// with the snapshot after it, which is mostly zeroes and compresses well.
+pub struct RewindBuffer {
+    capacity: usize,
+    interval: u32,
+    frames_until_snapshot: u32,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    /// Creates a buffer that holds up to `capacity` snapshots, taking one every `interval` frames
+    pub fn new(capacity: usize, interval: u32) -> RewindBuffer {
+        RewindBuffer {
+            capacity: capacity.max(1),
+            interval: interval.max(1),
+            frames_until_snapshot: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Should be called after every emulated frame
+    pub fn record_frame(&mut self, game_boy: &GameBoy) {
+        if self.frames_until_snapshot > 0 {
+            self.frames_until_snapshot -= 1;
+            return;
+        }
+        self.frames_until_snapshot = self.interval - 1;
+
+        // Turn the previous snapshot into a delta against the new one
+        let snapshot = game_boy.save_state();
+        if let Some(previous) = self.newest.take() {
+            self.deltas.push_back(encode_delta(&previous, &snapshot));
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(snapshot);
+    }
+
+    /// Steps the machine back to the previous snapshot. Returns false if there is nothing left to rewind to.
+    pub fn rewind(&mut self, game_boy: &mut GameBoy) -> bool {
+        let (Some(newest), Some(delta)) = (self.newest.as_ref(), self.deltas.pop_back()) else {
+            return false;
+        };
+        let previous = decode_delta(newest, &delta);
+
+        // This only fails if a different ROM was inserted since recording, in which case the history is useless
+        if game_boy.load_state(&previous).is_err() {
+            self.clear();
+            return false;
+        }
+        self.newest = Some(previous);
+        self.frames_until_snapshot = self.interval - 1;
+        true
+    }
+
+    /// Number of snapshots that can be rewound to
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.frames_until_snapshot = 0;
+    }
+}
+
+// A delta is the length of the older snapshot, followed by the XOR of both snapshots (the newer one padded with zeroes)
+// as runs of zeroes and literal bytes: [zero run length, literal length, literal bytes...] repeated.
+fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_varint(&mut delta, older.len());
+
+    let xor_at = |index: usize| older[index] ^ newer.get(index).copied().unwrap_or(0);
+    let mut index = 0;
+    while index < older.len() {
+        // Count the zeroes
+        let zero_start = index;
+        while index < older.len() && xor_at(index) == 0 {
+            index += 1;
+        }
+        // Then the literal bytes, until there's a run of zeroes long enough to be worth encoding
+        let literal_start = index;
+        while index < older.len()
+            && !(index + 4 <= older.len() && (index..index + 4).all(|i| xor_at(i) == 0))
+        {
+            index += 1;
+        }
+        write_varint(&mut delta, literal_start - zero_start);
+        write_varint(&mut delta, index - literal_start);
+        delta.extend((literal_start..index).map(xor_at));
+    }
+    delta
+}
+
+fn decode_delta(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let older_length = read_varint(delta, &mut position);
+
+    // Start with the newer snapshot, and XOR the literal bytes back in
+    let mut older = newer.to_vec();
+    older.resize(older_length, 0);
+    let mut index = 0;
+    while position < delta.len() {
+        index += read_varint(delta, &mut position);
+        let literal_length = read_varint(delta, &mut position);
+        for byte in &delta[position..position + literal_length] {
+            older[index] ^= byte;
+            index += 1;
+        }
+        position += literal_length;
+    }
+    older
+}
+
+// LEB128-style variable length integers, 7 bits per byte
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        data.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(older: &[u8], newer: &[u8]) -> Vec<u8> {
+        let delta = encode_delta(older, newer);
+        let decoded = decode_delta(newer, &delta);
+        assert!(decoded == older);
+        delta
+    }
+
+    #[test]
+    fn identical_snapshots() {
+        let snapshot = vec![0x5A; 1000];
+        let delta = round_trip(&snapshot, &snapshot);
+        // Just the length and one long run of zeroes
+        assert_eq!(delta.len(), 2 + 2 + 1);
+    }
+
+    #[test]
+    fn runs_longer_than_127() {
+        let older: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let mut newer = older.clone();
+        newer[0] ^= 0xFF;
+        newer[300..600].fill(0xEE);
+        newer[4999] ^= 0x01;
+        round_trip(&older, &newer);
+    }
+
+    #[test]
+    fn every_byte_changed() {
+        let older: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let newer: Vec<u8> = older.iter().map(|byte| !byte).collect();
+        round_trip(&older, &newer);
+    }
+
+    #[test]
+    fn different_lengths() {
+        let older: Vec<u8> = (0..700).map(|i| (i * 7) as u8).collect();
+        round_trip(&older, &older[..200]);
+        round_trip(&older[..200], &older);
+        round_trip(&older, &[]);
+    }
+
+    #[test]
+    fn varints() {
+        for value in [0, 1, 127, 128, 300, 16383, 16384, usize::MAX >> 1] {
+            let mut data = Vec::new();
+            write_varint(&mut data, value);
+            let mut position = 0;
+            assert_eq!(read_varint(&data, &mut position), value);
+            assert_eq!(position, data.len());
+        }
+    }
+}
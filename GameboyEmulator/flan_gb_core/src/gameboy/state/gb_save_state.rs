@@ -0,0 +1,414 @@
+use std::collections::VecDeque;
+
+use super::super::{GameBoy, OamEntry, PpuFifoElement};
+use super::gb_state_io::{SaveStateError, StateReader, StateWriter};
+
+const SAVE_STATE_MAGIC: &[u8; 8] = b"FLANGBSS";
+const SAVE_STATE_VERSION: u32 = 5;
+
+// Magic, version, and ROM hash
+const SAVE_STATE_HEADER_SIZE: usize = 8 + 4 + 8;
+
+impl GameBoy {
+    /// Captures the whole machine into a versioned save state
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+
+        // Header
+        state.bytes(SAVE_STATE_MAGIC);
+        state.u32(SAVE_STATE_VERSION);
+        state.u64(self.rom_hash);
+
+        self.write_state(&mut state);
+        state.data
+    }
+
+    /// Restores a save state made by `save_state`. If the save state can't be loaded, the machine is left untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(state);
+
+        // Check the header
+        let mut magic = [0u8; 8];
+        reader
+            .bytes(&mut magic)
+            .map_err(|_| SaveStateError::NotASaveState)?;
+        if &magic != SAVE_STATE_MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let version = reader.u32()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        if reader.u64()? != self.rom_hash {
+            return Err(SaveStateError::WrongRom);
+        }
+
+        // Keep a copy of the current state, so a broken save state doesn't leave the machine half loaded
+        let backup = self.save_state();
+        let result = self
+            .read_state(&mut reader)
+            .and_then(|_| match reader.is_empty() {
+                true => Ok(()),
+                false => Err(SaveStateError::Corrupted),
+            });
+        if result.is_err() {
+            self.read_state(&mut StateReader::new(&backup[SAVE_STATE_HEADER_SIZE..]))
+                .expect("Couldn't restore the machine after failing to load a save state");
+        }
+
+        // There's no telling which bytes the game wrote before the state was saved, so count them all
+        if let (Ok(()), Some(written)) = (&result, &mut self.written_memory) {
+            written.mark_all_written();
+        }
+        result
+    }
+
+    fn write_state(&self, state: &mut StateWriter) {
+        // Memory
+        state.vec(&self.eram);
+        state.bytes(&self.vram);
+        state.bytes(&self.wram);
+        state.bytes(&self.oam);
+        state.bytes(&self.io);
+        state.bytes(&self.hram);
+        state.bytes(&self.bg_palette_ram);
+        state.bytes(&self.obj_palette_ram);
+        self.mapper.write_state(state);
+
+        // CPU
+        state.u8(self.ie);
+        state.u8(self.ime);
+        state.u16(self.pc);
+        state.u16(self.sp);
+        for reg in [
+            self.reg_a, self.reg_f, self.reg_b, self.reg_c, self.reg_d, self.reg_e, self.reg_h,
+            self.reg_l,
+        ] {
+            state.u8(reg);
+        }
+
+        // PPU
+        state.u8(self.ppu_lx);
+        state.u8(self.ppu_ly);
+        state.u8(self.ppu_mode);
+        state.u16(self.ppu_dots_into_curr_mode);
+        state.u16(self.ppu_dots_into_curr_line);
+        state.usize(self.ppu_fifo.len());
+        for element in &self.ppu_fifo {
+            state.u8(element.color);
+            state.u8(element.source);
+            state.u8(element.palette);
+            state.bool(element.priority);
+            state.u8(element.oam_index);
+        }
+        state.u8(self.ppu_tilemap_x);
+        state.u8(self.ppu_tilemap_y);
+        state.u8(self.ppu_pixels_to_discard);
+        state.usize(self.ppu_sprite_buffer.len());
+        for sprite in &self.ppu_sprite_buffer {
+            state.u8(sprite.posy);
+            state.u8(sprite.posx);
+            state.u8(sprite.tile);
+            state.u8(sprite.attr);
+            state.u8(sprite.index);
+        }
+        for pixel in &self.framebuffer {
+            state.u32(*pixel);
+        }
+        state.bool(self.window_is_rendering);
+
+        // APU
+        for buffer in &self.apu_buffer {
+            for sample in buffer {
+                state.u16(*sample);
+            }
+        }
+        state.usize(self.apu_buffer_to_use);
+        state.usize(self.apu_buffer_write_index);
+        state.usize(self.apu_buffer_read_index);
+        state.bytes(&self.apu_sound_output);
+        state.u16(self.apu_pulse1_freq_counter);
+        state.u8(self.apu_pulse1_env_counter);
+        state.usize(self.apu_pulse1_duty_step);
+        state.u8(self.apu_pulse1_length_timer);
+        state.bool(self.apu_pulse1_enabled);
+        state.u8(self.apu_pulse1_curr_volume);
+        state.u16(self.apu_pulse2_freq_counter);
+        state.u8(self.apu_pulse2_env_counter);
+        state.usize(self.apu_pulse2_duty_step);
+        state.u8(self.apu_pulse2_length_timer);
+        state.bool(self.apu_pulse2_enabled);
+        state.u8(self.apu_pulse2_curr_volume);
+        state.u16(self.apu_wave_freq_counter);
+        state.u8(self.apu_wave_env_counter);
+        state.usize(self.apu_wave_duty_step);
+        state.u8(self.apu_wave_length_timer);
+        state.bool(self.apu_wave_enabled);
+        state.u16(self.apu_noise_freq_counter);
+        state.u8(self.apu_noise_env_counter);
+        state.usize(self.apu_noise_duty_step);
+        state.u8(self.apu_noise_length_timer);
+        state.bool(self.apu_noise_enabled);
+        state.u8(self.apu_noise_curr_volume);
+        state.u8(self.apu_pulse1_sweep_timer);
+        state.u16(self.apu_pulse1_sweep_shadow_freq);
+        state.bool(self.apu_pulse1_sweep_enable);
+        state.u32(self.apu_clock_timer);
+        state.u32(self.apu_clock);
+
+        // CGB
+        state.bool(self.cgb_mode);
+        state.usize(self.vram_bank);
+        state.usize(self.wram_bank);
+        state.bool(self.double_speed);
+        state.bool(self.double_speed_skip_cycle);
+        state.u16(self.hdma_source);
+        state.u16(self.hdma_destination);
+        state.u8(self.hdma_blocks_left);
+        state.bool(self.hdma_active);
+
+        // Misc
+        state.u32(self.curr_cycles_to_wait);
+        state.u8(self.last_opcode);
+        state.u32(self.last_opcode_cycles);
+        state.bool(self.new_instruction_tick);
+        state.bool(self.rom_chip_enabled);
+        state.u32(self.cpu_cycle_counter);
+        state.bool(self.is_halted);
+        state.bool(self.is_stopped);
+        state.u16(self.timer_div);
+        state.bool(self.timer_overflow);
+        state.u8(self.oam_dma_counter);
+        state.u16(self.oam_dma_source);
+        state.u32(self.serial_cycles_left);
+        state.u32(self.serial_poll_timer);
+        state.u8(self.joypad_state);
+    }
+
+    fn read_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        // Memory
+        let eram = state.vec()?;
+        if eram.len() != self.eram.len() {
+            return Err(SaveStateError::Corrupted);
+        }
+        self.eram = eram;
+        state.bytes(&mut self.vram)?;
+        state.bytes(&mut self.wram)?;
+        state.bytes(&mut self.oam)?;
+        state.bytes(&mut self.io)?;
+        state.bytes(&mut self.hram)?;
+        state.bytes(&mut self.bg_palette_ram)?;
+        state.bytes(&mut self.obj_palette_ram)?;
+        self.mapper.read_state(state)?;
+
+        // CPU
+        self.ie = state.u8()?;
+        self.ime = state.u8()?;
+        self.pc = state.u16()?;
+        self.sp = state.u16()?;
+        self.reg_a = state.u8()?;
+        self.reg_f = state.u8()?;
+        self.reg_b = state.u8()?;
+        self.reg_c = state.u8()?;
+        self.reg_d = state.u8()?;
+        self.reg_e = state.u8()?;
+        self.reg_h = state.u8()?;
+        self.reg_l = state.u8()?;
+
+        // PPU
+        // The PPU indexes the framebuffer and palettes with these, so make sure they're in range
+        self.ppu_lx = state.u8()?;
+        self.ppu_ly = state.u8()?;
+        self.ppu_mode = state.u8()?;
+        if self.ppu_lx > 160 || self.ppu_ly > 153 || self.ppu_mode > 3 {
+            return Err(SaveStateError::Corrupted);
+        }
+        self.ppu_dots_into_curr_mode = state.u16()?;
+        self.ppu_dots_into_curr_line = state.u16()?;
+        let fifo_length = state.usize()?;
+        if fifo_length > 16 {
+            return Err(SaveStateError::Corrupted);
+        }
+        let mut fifo = VecDeque::new();
+        for _ in 0..fifo_length {
+            let element = PpuFifoElement {
+                color: state.u8()?,
+                source: state.u8()?,
+                palette: state.u8()?,
+                priority: state.bool()?,
+                oam_index: state.u8()?,
+            };
+            if element.color > 3 || element.source > 2 || element.palette > 7 {
+                return Err(SaveStateError::Corrupted);
+            }
+            fifo.push_back(element);
+        }
+        self.ppu_fifo = fifo;
+        self.ppu_tilemap_x = state.u8()?;
+        self.ppu_tilemap_y = state.u8()?;
+        self.ppu_pixels_to_discard = state.u8()?;
+        let sprite_count = state.usize()?;
+        if sprite_count > 10 {
+            return Err(SaveStateError::Corrupted);
+        }
+        let mut sprites = Vec::new();
+        for _ in 0..sprite_count {
+            sprites.push(OamEntry {
+                posy: state.u8()?,
+                posx: state.u8()?,
+                tile: state.u8()?,
+                attr: state.u8()?,
+                index: state.u8()?,
+            });
+        }
+        self.ppu_sprite_buffer = sprites;
+        for pixel in &mut self.framebuffer {
+            *pixel = state.u32()?;
+        }
+        self.window_is_rendering = state.bool()?;
+
+        // APU
+        for buffer in &mut self.apu_buffer {
+            for sample in buffer {
+                *sample = state.u16()?;
+            }
+        }
+        self.apu_buffer_to_use = state.usize()? & 1;
+        self.apu_buffer_write_index = state.usize()? % (256 << 6);
+        self.apu_buffer_read_index = state.usize()?;
+        state.bytes(&mut self.apu_sound_output)?;
+        self.apu_pulse1_freq_counter = state.u16()?;
+        self.apu_pulse1_env_counter = state.u8()?;
+        self.apu_pulse1_duty_step = state.usize()? % 8;
+        self.apu_pulse1_length_timer = state.u8()?;
+        self.apu_pulse1_enabled = state.bool()?;
+        self.apu_pulse1_curr_volume = state.u8()?;
+        self.apu_pulse2_freq_counter = state.u16()?;
+        self.apu_pulse2_env_counter = state.u8()?;
+        self.apu_pulse2_duty_step = state.usize()? % 8;
+        self.apu_pulse2_length_timer = state.u8()?;
+        self.apu_pulse2_enabled = state.bool()?;
+        self.apu_pulse2_curr_volume = state.u8()?;
+        self.apu_wave_freq_counter = state.u16()?;
+        self.apu_wave_env_counter = state.u8()?;
+        self.apu_wave_duty_step = state.usize()? % 32;
+        self.apu_wave_length_timer = state.u8()?;
+        self.apu_wave_enabled = state.bool()?;
+        self.apu_noise_freq_counter = state.u16()?;
+        self.apu_noise_env_counter = state.u8()?;
+        self.apu_noise_duty_step = state.usize()?;
+        self.apu_noise_length_timer = state.u8()?;
+        self.apu_noise_enabled = state.bool()?;
+        self.apu_noise_curr_volume = state.u8()?;
+        self.apu_pulse1_sweep_timer = state.u8()?;
+        self.apu_pulse1_sweep_shadow_freq = state.u16()?;
+        self.apu_pulse1_sweep_enable = state.bool()?;
+        self.apu_clock_timer = state.u32()?;
+        self.apu_clock = state.u32()?;
+
+        // CGB
+        self.cgb_mode = state.bool()?;
+        self.vram_bank = state.usize()? & 0x01;
+        // Same as writing SVBK, bank 0 selects bank 1
+        self.wram_bank = (state.usize()? & 0x07).max(1);
+        self.double_speed = state.bool()?;
+        self.double_speed_skip_cycle = state.bool()?;
+        self.hdma_source = state.u16()?;
+        self.hdma_destination = state.u16()?;
+        self.hdma_blocks_left = state.u8()?;
+        self.hdma_active = state.bool()?;
+
+        // Misc
+        self.curr_cycles_to_wait = state.u32()?;
+        self.last_opcode = state.u8()?;
+        self.last_opcode_cycles = state.u32()?;
+        self.new_instruction_tick = state.bool()?;
+        self.rom_chip_enabled = state.bool()?;
+        self.cpu_cycle_counter = state.u32()?;
+        self.is_halted = state.bool()?;
+        self.is_stopped = state.bool()?;
+        self.timer_div = state.u16()?;
+        self.timer_overflow = state.bool()?;
+        self.oam_dma_counter = state.u8()?.min(160);
+        self.oam_dma_source = state.u16()?;
+        self.serial_cycles_left = state.u32()?;
+        self.serial_poll_timer = state.u32()?;
+        self.joypad_state = state.u8()?;
+
+        // Samples that were produced before loading don't belong to this state anymore
+        self.apu_output.clear();
+        self.audio_synth.reset();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An MBC1 cartridge with RAM, that just spins in a loop
+    fn test_game_boy() -> GameBoy {
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x100..0x104].copy_from_slice(&[0xC3, 0x00, 0x01, 0x00]); // JP $0100
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x02;
+        let mut game_boy = GameBoy::new();
+        assert!(game_boy.load_rom(rom));
+        game_boy.skip_boot_rom();
+        game_boy
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut game_boy = test_game_boy();
+        for _ in 0..100_000 {
+            game_boy.step();
+        }
+        let state = game_boy.save_state();
+
+        let mut loaded = test_game_boy();
+        loaded.load_state(&state).unwrap();
+        assert!(loaded.save_state() == state);
+
+        // Anything that isn't saved would make the two drift apart
+        for _ in 0..100_000 {
+            game_boy.step();
+            loaded.step();
+        }
+        assert!(loaded.save_state() == game_boy.save_state());
+    }
+
+    #[test]
+    fn rejects_other_states() {
+        let mut game_boy = test_game_boy();
+        let mut state = game_boy.save_state();
+        state.truncate(state.len() - 1);
+        assert!(matches!(
+            game_boy.load_state(&state),
+            Err(SaveStateError::Corrupted)
+        ));
+        assert!(matches!(
+            game_boy.load_state(b"not a save state"),
+            Err(SaveStateError::NotASaveState)
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_ppu_mode() {
+        let mut game_boy = test_game_boy();
+        // Find the mode byte by changing only the mode between two saves
+        game_boy.ppu_mode = 1;
+        let mut state = game_boy.save_state();
+        game_boy.ppu_mode = 2;
+        let other = game_boy.save_state();
+        let offset = (0..state.len()).find(|&i| state[i] != other[i]).unwrap();
+
+        state[offset] = 4;
+        assert!(matches!(
+            game_boy.load_state(&state),
+            Err(SaveStateError::Corrupted)
+        ));
+        assert_eq!(game_boy.ppu_mode, 2);
+    }
+}
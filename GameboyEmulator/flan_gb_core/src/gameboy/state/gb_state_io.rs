@@ -0,0 +1,128 @@
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SaveStateError {
+    NotASaveState,
+    UnsupportedVersion(u32),
+    WrongRom,
+    Corrupted,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "this file is not a save state"),
+            SaveStateError::UnsupportedVersion(version) => {
+                write!(f, "save state version {version} is not supported")
+            }
+            SaveStateError::WrongRom => write!(f, "this save state was made with a different ROM"),
+            SaveStateError::Corrupted => write!(f, "this save state is corrupted"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+// Writes values into a save state, all little endian
+pub struct StateWriter {
+    pub data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+
+    // Fixed size data, the reader has to know the length
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(value);
+    }
+
+    // Variable size data, prefixed with the length
+    pub fn vec(&mut self, value: &[u8]) {
+        self.usize(value.len());
+        self.bytes(value);
+    }
+}
+
+// Reads values back out of a save state, in the same order they were written
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.data.len() < length {
+            return Err(SaveStateError::Corrupted);
+        }
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SaveStateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, SaveStateError> {
+        Ok(self.u32()? as usize)
+    }
+
+    pub fn bytes(&mut self, target: &mut [u8]) -> Result<(), SaveStateError> {
+        target.copy_from_slice(self.take(target.len())?);
+        Ok(())
+    }
+
+    pub fn vec(&mut self) -> Result<Vec<u8>, SaveStateError> {
+        let length = self.usize()?;
+        Ok(self.take(length)?.to_vec())
+    }
+}
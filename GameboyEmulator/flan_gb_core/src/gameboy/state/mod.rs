@@ -0,0 +1,4 @@
+pub mod gb_movie;
+pub mod gb_rewind;
+pub mod gb_save_state;
+pub mod gb_state_io;
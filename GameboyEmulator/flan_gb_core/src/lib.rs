@@ -0,0 +1,21 @@
+//! Headless Game Boy emulator core.
+//!
+//! This crate has no windowing or audio dependencies. Frontends create a [`GameBoy`], insert a
+//! cartridge, feed it an [`InputState`] every frame, and then pull out the framebuffer and the
+//! audio samples that were produced.
+
+mod gameboy;
+
+pub use gameboy::{
+    disassemble, AudioMode, BreakReason, GameBoy, ImageComparison, ImageError, InputState,
+    Instruction, LinkCable, MemoryInit, Movie, MovieError, Printer, Registers, RewindBuffer,
+    SaveStateError, SerialDevice, WatchKind, FAST_AUDIO_SAMPLE_RATE,
+};
+
+/// Width of the Game Boy screen in pixels
+pub const SCREEN_WIDTH: usize = 160;
+/// Height of the Game Boy screen in pixels
+pub const SCREEN_HEIGHT: usize = 144;
+/// Sample rate of the audio produced by the APU until told otherwise, in Hz. Samples are
+/// interleaved stereo.
+pub const DEFAULT_AUDIO_SAMPLE_RATE: u32 = 48000;
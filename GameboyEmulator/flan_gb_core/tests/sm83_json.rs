@@ -0,0 +1,199 @@
+//! Runs the community SM83 single-step tests, one JSON file per opcode, against the CPU.
+//!
+//! The test files aren't included in the repository. Put them in `tests/sm83/` (for example the
+//! `v1` folder of the SingleStepTests sm83 repository), or point `FLAN_SM83_TESTS` at them.
+//! Each case sets up the registers and a flat 64 KiB RAM bus, runs one instruction, and checks
+//! the registers, memory and M-cycle count afterwards.
+//!
+//! The M-cycle count is the total the CPU waits for, internal cycles included, compared against
+//! the length of the `cycles` array. What happens on the bus during each of those cycles isn't
+//! compared.
+//!
+//! Without test files the test is skipped. Otherwise any mismatch fails it, after every opcode
+//! with failing cases has been listed along with its first mismatch.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use flan_gb_core::{GameBoy, Registers};
+use serde_json::Value;
+
+struct OpcodeResult {
+    name: String,
+    cases: usize,
+    failures: usize,
+    first_failure: Option<String>,
+}
+
+#[test]
+fn sm83_json() {
+    let test_dir = match env::var_os("FLAN_SM83_TESTS") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("sm83"),
+    };
+    let Ok(entries) = fs::read_dir(&test_dir) else {
+        println!("No SM83 tests in {}, skipping", test_dir.display());
+        return;
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    files.sort();
+
+    let mut game_boy = GameBoy::new();
+    game_boy.use_test_bus();
+
+    let mut results = Vec::new();
+    for path in files {
+        let text = fs::read_to_string(&path).expect("Couldn't read test file");
+        let cases: Vec<Value> = serde_json::from_str(&text).expect("Couldn't parse test file");
+
+        let mut result = OpcodeResult {
+            name: path.file_stem().unwrap().to_string_lossy().to_string(),
+            cases: cases.len(),
+            failures: 0,
+            first_failure: None,
+        };
+        for case in &cases {
+            if let Err(mismatch) = run_case(&mut game_boy, case) {
+                result.failures += 1;
+                result.first_failure.get_or_insert(format!(
+                    "{}: {mismatch}",
+                    case["name"].as_str().unwrap_or("?")
+                ));
+            }
+        }
+        results.push(result);
+    }
+
+    let summary = summary_table(&results);
+    println!("{summary}");
+    let summary_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("sm83_summary.md");
+    match fs::write(&summary_path, &summary) {
+        Ok(()) => println!("Summary written to {}", summary_path.display()),
+        Err(e) => println!("Unable to write summary to {}: {e}", summary_path.display()),
+    }
+
+    let failed = results.iter().filter(|result| result.failures > 0).count();
+    assert_eq!(failed, 0, "{failed} opcodes have failing cases");
+}
+
+// Returns a description of everything that didn't match
+fn run_case(game_boy: &mut GameBoy, case: &Value) -> Result<(), String> {
+    let initial = &case["initial"];
+    let expected = &case["final"];
+
+    // Set up the CPU and the bytes it's going to touch
+    game_boy.set_registers(&read_registers(initial));
+    for (address, value) in read_ram(initial) {
+        game_boy.write_memory(address, value);
+    }
+
+    let cycles = game_boy.step_instruction() as usize;
+
+    // Compare everything
+    let mut mismatches = Vec::new();
+    let got = game_boy.registers();
+    let wanted = read_registers(expected);
+    let registers = [
+        ("A", got.a as u16, wanted.a as u16),
+        ("F", got.f as u16, wanted.f as u16),
+        ("B", got.b as u16, wanted.b as u16),
+        ("C", got.c as u16, wanted.c as u16),
+        ("D", got.d as u16, wanted.d as u16),
+        ("E", got.e as u16, wanted.e as u16),
+        ("H", got.h as u16, wanted.h as u16),
+        ("L", got.l as u16, wanted.l as u16),
+        ("SP", got.sp, wanted.sp),
+        ("PC", got.pc, wanted.pc),
+        ("IME", got.ime as u16, wanted.ime as u16),
+    ];
+    for (name, got, wanted) in registers {
+        if got != wanted {
+            mismatches.push(format!("{name} is ${got:02X}, expected ${wanted:02X}"));
+        }
+    }
+    for (address, wanted) in read_ram(expected) {
+        let got = game_boy.read_memory(address);
+        if got != wanted {
+            mismatches.push(format!(
+                "[${address:04X}] is ${got:02X}, expected ${wanted:02X}"
+            ));
+        }
+    }
+    let wanted_cycles = case["cycles"].as_array().map_or(0, |cycles| cycles.len());
+    if cycles != wanted_cycles {
+        mismatches.push(format!(
+            "took {cycles} M-cycles (bus and internal), expected {wanted_cycles}"
+        ));
+    }
+
+    // Clean up so the next case starts from zeroed memory
+    for (address, _) in read_ram(initial).into_iter().chain(read_ram(expected)) {
+        game_boy.write_memory(address, 0);
+    }
+
+    match mismatches.is_empty() {
+        true => Ok(()),
+        false => Err(mismatches.join(", ")),
+    }
+}
+
+fn read_registers(state: &Value) -> Registers {
+    let byte = |name: &str| state[name].as_u64().unwrap_or(0) as u8;
+    let short = |name: &str| state[name].as_u64().unwrap_or(0) as u16;
+    Registers {
+        a: byte("a"),
+        f: byte("f"),
+        b: byte("b"),
+        c: byte("c"),
+        d: byte("d"),
+        e: byte("e"),
+        h: byte("h"),
+        l: byte("l"),
+        sp: short("sp"),
+        pc: short("pc"),
+        ime: byte("ime") != 0,
+    }
+}
+
+fn read_ram(state: &Value) -> Vec<(u16, u8)> {
+    let Some(entries) = state["ram"].as_array() else {
+        return Vec::new();
+    };
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let address = entry.get(0)?.as_u64()? as u16;
+            let value = entry.get(1)?.as_u64()? as u8;
+            Some((address, value))
+        })
+        .collect()
+}
+
+fn summary_table(results: &[OpcodeResult]) -> String {
+    let mut table = String::from("| Opcode | Failed | First failure |\n|---|---|---|\n");
+    for result in results.iter().filter(|result| result.failures > 0) {
+        table += &format!(
+            "| {} | {}/{} | {} |\n",
+            result.name,
+            result.failures,
+            result.cases,
+            result.first_failure.as_deref().unwrap_or("")
+        );
+    }
+
+    let passed = results.iter().filter(|result| result.failures == 0).count();
+    table += &format!("\n{passed}/{} opcodes passed\n", results.len());
+    table += "M-cycle counts include internal cycles, bus activity per cycle isn't compared\n";
+    table
+}
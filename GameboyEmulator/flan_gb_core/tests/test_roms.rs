@@ -0,0 +1,257 @@
+//! Runs the Blargg and Mooneye test ROM suites on the headless core and reports which ROMs pass.
+//!
+//! The ROMs aren't included in the repository. Put them in `tests/roms/blargg/` and
+//! `tests/roms/mooneye/` (subfolders are fine), or point `FLAN_TEST_ROMS` at a folder laid out
+//! the same way. Suites that aren't there are skipped.
+//!
+//! A summary table is printed and written to `test_rom_summary.md` in the cargo target temp
+//! folder. Any ROM that fails, times out or panics fails the test, unless
+//! `FLAN_TEST_ROMS_ALLOW_FAILURES` is set to only get the table. `FLAN_TEST_ROMS_TIMEOUT`
+//! overrides the per-ROM timeout in frames.
+//! Running with `--release` is a lot faster.
+
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use flan_gb_core::GameBoy;
+
+// How long a single ROM gets before it counts as a timeout. The full cpu_instrs takes close to a minute.
+const BLARGG_TIMEOUT_FRAMES: u32 = 60 * 90;
+const MOONEYE_TIMEOUT_FRAMES: u32 = 60 * 20;
+
+// Mooneye ROMs load these into B, C, D, E, H and L when they pass, and 0x42 everywhere when they fail
+const MOONEYE_PASS_REGISTERS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL_REGISTERS: [u8; 6] = [0x42; 6];
+
+// Newer Blargg ROMs put their result at 0xA000, once the signature after it is present
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_RUNNING: u8 = 0x80;
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    Timeout,
+    Panic(String),
+}
+
+// Runs a ROM until it reports a result or the timeout in frames runs out, returns how many frames it took
+type SuiteRunner = fn(&mut GameBoy, u32) -> (Outcome, u32);
+
+struct RomResult {
+    suite: &'static str,
+    name: String,
+    outcome: Outcome,
+    frames: u32,
+}
+
+#[test]
+fn test_roms() {
+    let rom_dir = match env::var_os("FLAN_TEST_ROMS") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("roms"),
+    };
+    let timeout_override = env::var("FLAN_TEST_ROMS_TIMEOUT")
+        .ok()
+        .and_then(|frames| frames.parse().ok());
+
+    let mut results = Vec::new();
+    let suites: [(&str, u32, SuiteRunner); 2] = [
+        ("blargg", BLARGG_TIMEOUT_FRAMES, run_blargg),
+        ("mooneye", MOONEYE_TIMEOUT_FRAMES, run_mooneye),
+    ];
+    for (suite, timeout, run) in suites {
+        let suite_dir = rom_dir.join(suite);
+        if !suite_dir.is_dir() {
+            println!("No {suite} ROMs in {}, skipping", suite_dir.display());
+            continue;
+        }
+
+        for path in find_roms(&suite_dir) {
+            let rom = fs::read(&path).expect("Couldn't read test ROM");
+            let timeout = timeout_override.unwrap_or(timeout);
+
+            // A ROM that crashes the emulator shouldn't take the rest of the suite down with it
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut game_boy = GameBoy::new();
+                if !game_boy.load_rom(rom) {
+                    return (Outcome::Fail("ROM is too small".to_string()), 0);
+                }
+                game_boy.skip_boot_rom();
+                game_boy.set_serial_capture(true);
+                run(&mut game_boy, timeout)
+            }));
+            let (outcome, frames) = match result {
+                Ok(result) => result,
+                Err(payload) => {
+                    let message = payload
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    (Outcome::Panic(message), 0)
+                }
+            };
+            let name = path.strip_prefix(&suite_dir).unwrap_or(&path);
+            results.push(RomResult {
+                suite,
+                name: name.display().to_string(),
+                outcome,
+                frames,
+            });
+        }
+    }
+
+    if results.is_empty() {
+        println!("0 ROMs found in {}, nothing was tested", rom_dir.display());
+        return;
+    }
+
+    let summary = summary_table(&results);
+    println!("{summary}");
+    let summary_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_rom_summary.md");
+    match fs::write(&summary_path, &summary) {
+        Ok(()) => println!("Summary written to {}", summary_path.display()),
+        Err(e) => println!("Unable to write summary to {}: {e}", summary_path.display()),
+    }
+
+    if env::var_os("FLAN_TEST_ROMS_ALLOW_FAILURES").is_none() {
+        let failed = results
+            .iter()
+            .filter(|result| !matches!(result.outcome, Outcome::Pass))
+            .count();
+        assert_eq!(failed, 0, "{failed} test ROMs did not pass");
+    }
+}
+
+fn run_blargg(game_boy: &mut GameBoy, timeout: u32) -> (Outcome, u32) {
+    let mut serial = String::new();
+    for frame in 1..=timeout {
+        game_boy.run_frame();
+
+        // Older ROMs only report over serial
+        serial.extend(
+            game_boy
+                .take_serial_output()
+                .iter()
+                .map(|byte| *byte as char),
+        );
+        if serial.contains("Passed") {
+            return (Outcome::Pass, frame);
+        }
+        if serial.contains("Failed") {
+            return (Outcome::Fail(last_line(&serial)), frame);
+        }
+
+        // Newer ones also write it to cartridge RAM
+        let signature = [0xA001, 0xA002, 0xA003].map(|address| game_boy.read_memory(address));
+        let status = game_boy.read_memory(0xA000);
+        if signature == BLARGG_SIGNATURE && status != BLARGG_RUNNING {
+            if status == 0 {
+                return (Outcome::Pass, frame);
+            }
+            let text = read_c_string(game_boy, 0xA004);
+            return (
+                Outcome::Fail(format!("result {status:02X}: {}", last_line(&text))),
+                frame,
+            );
+        }
+    }
+    (Outcome::Timeout, timeout)
+}
+
+fn run_mooneye(game_boy: &mut GameBoy, timeout: u32) -> (Outcome, u32) {
+    // Mooneye ROMs run ld b, b once they've stored their result in the registers
+    game_boy.set_debugger_enabled(true);
+    game_boy.set_break_on_ld_b_b(true);
+
+    for frame in 1..=timeout {
+        game_boy.run_frame();
+        if game_boy.debugger_paused().is_none() {
+            continue;
+        }
+
+        let r = game_boy.registers();
+        let registers = [r.b, r.c, r.d, r.e, r.h, r.l];
+        if registers == MOONEYE_PASS_REGISTERS {
+            return (Outcome::Pass, frame);
+        }
+        if registers == MOONEYE_FAIL_REGISTERS {
+            return (
+                Outcome::Fail("failure pattern in registers".to_string()),
+                frame,
+            );
+        }
+        return (
+            Outcome::Fail(format!(
+                "unexpected registers B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X}",
+                r.b, r.c, r.d, r.e, r.h, r.l
+            )),
+            frame,
+        );
+    }
+    (Outcome::Timeout, timeout)
+}
+
+fn find_roms(dir: &Path) -> Vec<PathBuf> {
+    let mut roms = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return roms;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            roms.extend(find_roms(&path));
+        } else if matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("gb" | "gbc")
+        ) {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    roms
+}
+
+fn read_c_string(game_boy: &GameBoy, start: u16) -> String {
+    (start..0xC000)
+        .map(|address| game_boy.read_memory(address))
+        .take_while(|byte| *byte != 0)
+        .map(|byte| byte as char)
+        .collect()
+}
+
+fn last_line(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .unwrap_or("")
+        .to_string()
+}
+
+fn summary_table(results: &[RomResult]) -> String {
+    let mut table = String::from("| Suite | ROM | Result | Frames |\n|---|---|---|---|\n");
+    for result in results {
+        let outcome = match &result.outcome {
+            Outcome::Pass => "pass".to_string(),
+            Outcome::Fail(reason) => format!("FAIL ({reason})"),
+            Outcome::Timeout => "TIMEOUT".to_string(),
+            Outcome::Panic(message) => format!("PANIC ({message})"),
+        };
+        table += &format!(
+            "| {} | {} | {outcome} | {} |\n",
+            result.suite, result.name, result.frames
+        );
+    }
+
+    let passed = results
+        .iter()
+        .filter(|result| matches!(result.outcome, Outcome::Pass))
+        .count();
+    table += &format!("\n{passed}/{} passed\n", results.len());
+    table
+}
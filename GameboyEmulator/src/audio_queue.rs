@@ -0,0 +1,83 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::Source;
+
+/// Interleaved stereo samples waiting to be played. The emulator pushes into it, and the audio
+/// thread plays from an [`AudioQueueSource`] made with `source`.
+#[derive(Clone)]
+pub struct AudioQueue {
+    samples: Arc<Mutex<VecDeque<i16>>>,
+    sample_rate: u32,
+}
+
+impl AudioQueue {
+    pub fn new(sample_rate: u32) -> AudioQueue {
+        AudioQueue {
+            samples: Arc::new(Mutex::new(VecDeque::new())),
+            sample_rate,
+        }
+    }
+
+    pub fn source(&self) -> AudioQueueSource {
+        AudioQueueSource {
+            queue: self.clone(),
+            right: None,
+        }
+    }
+
+    pub fn push(&self, samples: &[i16]) {
+        self.samples.lock().unwrap().extend(samples);
+    }
+
+    /// How long it takes to play everything that's waiting
+    pub fn buffered(&self) -> Duration {
+        let frames = self.samples.lock().unwrap().len() / 2;
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+}
+
+pub struct AudioQueueSource {
+    queue: AudioQueue,
+    right: Option<i16>,
+}
+
+impl Iterator for AudioQueueSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+
+        // Take both sides at once, so running dry can't swap left and right
+        let mut samples = self.queue.samples.lock().unwrap();
+        let (left, right) = match samples.len() >= 2 {
+            true => (samples.pop_front(), samples.pop_front()),
+            false => (None, None),
+        };
+        self.right = Some(right.unwrap_or(0));
+        Some(left.unwrap_or(0))
+    }
+}
+
+impl Source for AudioQueueSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.queue.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
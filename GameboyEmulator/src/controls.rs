@@ -0,0 +1,702 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use flan_gb_core::InputState;
+use minifb::{Key, KeyRepeat, Window};
+
+use crate::gamepad::{GamepadInput, Gamepads};
+
+// Every key a binding can use, named in the config file the way minifb names them
+const KEYS: [Key; 106] = [
+    Key::Key0,
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::F13,
+    Key::F14,
+    Key::F15,
+    Key::Down,
+    Key::Left,
+    Key::Right,
+    Key::Up,
+    Key::Apostrophe,
+    Key::Backquote,
+    Key::Backslash,
+    Key::Comma,
+    Key::Equal,
+    Key::LeftBracket,
+    Key::Minus,
+    Key::Period,
+    Key::RightBracket,
+    Key::Semicolon,
+    Key::Slash,
+    Key::Backspace,
+    Key::Delete,
+    Key::End,
+    Key::Enter,
+    Key::Escape,
+    Key::Home,
+    Key::Insert,
+    Key::Menu,
+    Key::PageDown,
+    Key::PageUp,
+    Key::Pause,
+    Key::Space,
+    Key::Tab,
+    Key::NumLock,
+    Key::CapsLock,
+    Key::ScrollLock,
+    Key::LeftShift,
+    Key::RightShift,
+    Key::LeftCtrl,
+    Key::RightCtrl,
+    Key::NumPad0,
+    Key::NumPad1,
+    Key::NumPad2,
+    Key::NumPad3,
+    Key::NumPad4,
+    Key::NumPad5,
+    Key::NumPad6,
+    Key::NumPad7,
+    Key::NumPad8,
+    Key::NumPad9,
+    Key::NumPadDot,
+    Key::NumPadSlash,
+    Key::NumPadAsterisk,
+    Key::NumPadMinus,
+    Key::NumPadPlus,
+    Key::NumPadEnter,
+    Key::LeftAlt,
+    Key::RightAlt,
+    Key::LeftSuper,
+    Key::RightSuper,
+];
+
+pub const SAVE_STATE_SLOTS: usize = SAVE_STATE_SLOT_KEYS.len();
+
+// How far a stick has to be pushed to count as a direction, from 0 to 1
+const DEFAULT_AXIS_THRESHOLD: f32 = 0.5;
+
+// The keys the save state slots are on by default
+const SAVE_STATE_SLOT_KEYS: [Key; 9] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+];
+
+const CONFIG_HEADER: &str = "\
+# Controls for Flan's Game Boy Emulator
+#
+# Every line binds an action to one or more keys, separated by commas. Hotkeys can hold Shift, Ctrl
+# or Alt as well, like Shift+F1. Leave the keys empty to unbind an action.
+#
+# Keys are named A-Z, Key0-Key9, F1-F15, Up, Down, Left, Right, Enter, Space, Tab, Backspace,
+# Escape, Insert, Delete, Home, End, PageUp, PageDown, Minus, Equal, Comma, Period, Slash,
+# Backslash, Semicolon, Apostrophe, Backquote, LeftBracket, RightBracket, LeftShift, RightShift,
+# LeftCtrl, RightCtrl, LeftAlt, RightAlt, NumPad0-NumPad9, NumPadDot, NumPadPlus, NumPadMinus,
+# NumPadAsterisk, NumPadSlash and NumPadEnter.
+#
+# The [gamepad] section binds actions to gamepad buttons and axes the same way. They're named
+# Button0, Button1 and so on, and Axis0+ or Axis0- for pushing an axis one way or the other.
+# axis_threshold is how far an axis has to be pushed to count, between 0.0 and 1.0.
+";
+
+/// Something a key can be bound to, either a Game Boy button or an emulator hotkey
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    Start,
+    Select,
+    LoadState(usize),
+    SaveState(usize),
+    Rewind,
+    FastForward,
+    SlowDown,
+    SpeedUp,
+    Pause,
+    FrameAdvance,
+    Reset,
+    Screenshot,
+    RecordAudio,
+    Debugger,
+    Rebind,
+}
+
+impl Action {
+    const BUTTONS: [Action; 8] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::A,
+        Action::B,
+        Action::Start,
+        Action::Select,
+    ];
+
+    fn all() -> Vec<Action> {
+        let mut actions = Action::BUTTONS.to_vec();
+        actions.extend((1..=SAVE_STATE_SLOTS).map(Action::LoadState));
+        actions.extend((1..=SAVE_STATE_SLOTS).map(Action::SaveState));
+        actions.extend([
+            Action::Rewind,
+            Action::FastForward,
+            Action::SlowDown,
+            Action::SpeedUp,
+            Action::Pause,
+            Action::FrameAdvance,
+            Action::Reset,
+            Action::Screenshot,
+            Action::RecordAudio,
+            Action::Debugger,
+            Action::Rebind,
+        ]);
+        actions
+    }
+
+    fn is_button(self) -> bool {
+        Action::BUTTONS.contains(&self)
+    }
+
+    /// What the action is called in the config file
+    fn name(self) -> String {
+        match self {
+            Action::Up => "up".to_string(),
+            Action::Down => "down".to_string(),
+            Action::Left => "left".to_string(),
+            Action::Right => "right".to_string(),
+            Action::A => "a".to_string(),
+            Action::B => "b".to_string(),
+            Action::Start => "start".to_string(),
+            Action::Select => "select".to_string(),
+            Action::LoadState(slot) => format!("load_state_{slot}"),
+            Action::SaveState(slot) => format!("save_state_{slot}"),
+            Action::Rewind => "rewind".to_string(),
+            Action::FastForward => "fast_forward".to_string(),
+            Action::SlowDown => "slow_down".to_string(),
+            Action::SpeedUp => "speed_up".to_string(),
+            Action::Pause => "pause".to_string(),
+            Action::FrameAdvance => "frame_advance".to_string(),
+            Action::Reset => "reset".to_string(),
+            Action::Screenshot => "screenshot".to_string(),
+            Action::RecordAudio => "record_audio".to_string(),
+            Action::Debugger => "debugger".to_string(),
+            Action::Rebind => "rebind".to_string(),
+        }
+    }
+
+    // Laid out for an Xbox-style controller, which most gamepads show up as. The d-pad is
+    // usually axis 6 and 7, and the left stick axis 0 and 1.
+    fn default_gamepad_bindings(self) -> Vec<GamepadInput> {
+        match self {
+            Action::Up => vec![GamepadInput::AxisNegative(7), GamepadInput::AxisNegative(1)],
+            Action::Down => vec![GamepadInput::AxisPositive(7), GamepadInput::AxisPositive(1)],
+            Action::Left => vec![GamepadInput::AxisNegative(6), GamepadInput::AxisNegative(0)],
+            Action::Right => vec![GamepadInput::AxisPositive(6), GamepadInput::AxisPositive(0)],
+            Action::A => vec![GamepadInput::Button(1)],
+            Action::B => vec![GamepadInput::Button(0)],
+            Action::Select => vec![GamepadInput::Button(6)],
+            Action::Start => vec![GamepadInput::Button(7)],
+            Action::Rewind => vec![GamepadInput::Button(4)],
+            Action::FastForward => vec![GamepadInput::Button(5)],
+            _ => Vec::new(),
+        }
+    }
+
+    fn default_bindings(self) -> Vec<Binding> {
+        match self {
+            Action::Up => vec![Binding::key(Key::Up)],
+            Action::Down => vec![Binding::key(Key::Down)],
+            Action::Left => vec![Binding::key(Key::Left)],
+            Action::Right => vec![Binding::key(Key::Right)],
+            Action::A => vec![Binding::key(Key::X)],
+            Action::B => vec![Binding::key(Key::Z)],
+            Action::Start => vec![Binding::key(Key::Enter)],
+            Action::Select => vec![Binding::key(Key::RightShift)],
+            Action::LoadState(slot) => vec![Binding::key(SAVE_STATE_SLOT_KEYS[slot - 1])],
+            Action::SaveState(slot) => vec![Binding {
+                shift: true,
+                ..Binding::key(SAVE_STATE_SLOT_KEYS[slot - 1])
+            }],
+            Action::Rewind => vec![Binding::key(Key::Backspace)],
+            Action::FastForward => vec![Binding::key(Key::Tab)],
+            Action::SlowDown => vec![Binding::key(Key::Minus)],
+            Action::SpeedUp => vec![Binding::key(Key::Equal)],
+            Action::Pause => vec![Binding::key(Key::P)],
+            Action::FrameAdvance => vec![Binding::key(Key::Period)],
+            Action::Reset => vec![Binding {
+                ctrl: true,
+                ..Binding::key(Key::R)
+            }],
+            Action::Screenshot => vec![Binding::key(Key::F10)],
+            Action::RecordAudio => vec![Binding::key(Key::F11)],
+            Action::Debugger => vec![Binding::key(Key::F12)],
+            Action::Rebind => vec![Binding {
+                ctrl: true,
+                ..Binding::key(Key::K)
+            }],
+        }
+    }
+}
+
+/// A key, and the modifiers that have to be held along with it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Binding {
+    key: Key,
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+}
+
+impl Binding {
+    fn key(key: Key) -> Binding {
+        Binding {
+            key,
+            shift: false,
+            ctrl: false,
+            alt: false,
+        }
+    }
+
+    fn parse(text: &str) -> Option<Binding> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key_name = parts.pop()?;
+        let mut binding = Binding::key(
+            *KEYS
+                .iter()
+                .find(|key| format!("{key:?}").eq_ignore_ascii_case(key_name))?,
+        );
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "shift" => binding.shift = true,
+                "ctrl" => binding.ctrl = true,
+                "alt" => binding.alt = true,
+                _ => return None,
+            }
+        }
+        Some(binding)
+    }
+
+    fn modifier_count(&self) -> usize {
+        self.shift as usize + self.ctrl as usize + self.alt as usize
+    }
+
+    // Either side's modifier counts
+    fn modifiers_held(&self, window: &Window) -> bool {
+        let held = |left, right| window.is_key_down(left) || window.is_key_down(right);
+        (!self.shift || held(Key::LeftShift, Key::RightShift))
+            && (!self.ctrl || held(Key::LeftCtrl, Key::RightCtrl))
+            && (!self.alt || held(Key::LeftAlt, Key::RightAlt))
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+/// Which keys and gamepad inputs are bound to which action, and the gamepads they're read from
+pub struct Controls {
+    bindings: Vec<(Action, Vec<Binding>)>,
+    gamepad_bindings: Vec<(Action, Vec<GamepadInput>)>,
+    axis_threshold: f32,
+    gamepads: Gamepads,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            bindings: Action::all()
+                .into_iter()
+                .map(|action| (action, action.default_bindings()))
+                .collect(),
+            gamepad_bindings: Action::all()
+                .into_iter()
+                .map(|action| (action, action.default_gamepad_bindings()))
+                .collect(),
+            axis_threshold: DEFAULT_AXIS_THRESHOLD,
+            gamepads: Gamepads::default(),
+        }
+    }
+}
+
+impl Controls {
+    /// Loads the controls from a config file, writing one with the defaults if there isn't one yet
+    pub fn load_or_create(path: &Path) -> Controls {
+        if !path.exists() {
+            let controls = Controls::default();
+            match controls.save(path) {
+                Ok(()) => println!("Wrote the default controls to \"{}\"", path.display()),
+                Err(e) => println!("Unable to write controls \"{}\": {e}", path.display()),
+            }
+            return controls;
+        }
+
+        match Controls::load(path) {
+            Ok(controls) => {
+                println!("Loaded controls from \"{}\"", path.display());
+                controls.warn_about_conflicts();
+                controls
+            }
+            Err(e) => {
+                println!("{e}, using the default controls");
+                Controls::default()
+            }
+        }
+    }
+
+    /// Reads a config file. Actions it doesn't mention keep their default keys.
+    pub fn load(path: &Path) -> Result<Controls, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read controls \"{}\": {e}", path.display()))?;
+
+        let mut controls = Controls::default();
+        let mut in_gamepad_section = false;
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            // Sections only group the lines for the reader, every action has its own name. Only
+            // the gamepad section is different, it binds gamepad inputs instead of keys.
+            if let Some(section) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                in_gamepad_section = section.trim().eq_ignore_ascii_case("gamepad");
+                continue;
+            }
+
+            let error =
+                |message: &str| format!("{}:{}: {message}", path.display(), line_number + 1);
+            let (name, keys) = line
+                .split_once('=')
+                .ok_or_else(|| error(&format!("expected \"action = keys\", got \"{line}\"")))?;
+            let name = name.trim().to_ascii_lowercase();
+            if in_gamepad_section {
+                controls.load_gamepad_line(&name, keys.trim(), error)?;
+                continue;
+            }
+            let (action, bindings) = controls
+                .bindings
+                .iter_mut()
+                .find(|(action, _)| action.name() == name)
+                .ok_or_else(|| error(&format!("unknown action \"{name}\"")))?;
+
+            bindings.clear();
+            for text in keys
+                .split(',')
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+            {
+                let binding = Binding::parse(text)
+                    .ok_or_else(|| error(&format!("unknown key \"{text}\"")))?;
+                if action.is_button() && binding.modifier_count() > 0 {
+                    return Err(error("buttons can't use Shift, Ctrl or Alt"));
+                }
+                bindings.push(binding);
+            }
+        }
+        Ok(controls)
+    }
+
+    fn load_gamepad_line(
+        &mut self,
+        name: &str,
+        value: &str,
+        error: impl Fn(&str) -> String,
+    ) -> Result<(), String> {
+        if name == "axis_threshold" {
+            self.axis_threshold = value
+                .parse()
+                .ok()
+                .filter(|&threshold: &f32| threshold > 0.0 && threshold <= 1.0)
+                .ok_or_else(|| error("axis_threshold has to be a number between 0.0 and 1.0"))?;
+            return Ok(());
+        }
+
+        let (_, inputs) = self
+            .gamepad_bindings
+            .iter_mut()
+            .find(|(action, _)| action.name() == name)
+            .ok_or_else(|| error(&format!("unknown action \"{name}\"")))?;
+        inputs.clear();
+        for text in value
+            .split(',')
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+        {
+            inputs.push(
+                GamepadInput::parse(text)
+                    .ok_or_else(|| error(&format!("unknown gamepad input \"{text}\"")))?,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut text = CONFIG_HEADER.to_string();
+        for (index, (action, bindings)) in self.bindings.iter().enumerate() {
+            match index {
+                0 => text.push_str("\n[buttons]\n"),
+                8 => text.push_str("\n[hotkeys]\n"),
+                _ => (),
+            }
+            let keys: Vec<String> = bindings.iter().map(Binding::to_string).collect();
+            let line = format!("{} = {}", action.name(), keys.join(", "));
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+
+        // Most hotkeys aren't on the gamepad, so only write the ones that are, or were by default
+        text.push_str(&format!(
+            "\n[gamepad]\naxis_threshold = {}\n",
+            self.axis_threshold
+        ));
+        for (action, inputs) in &self.gamepad_bindings {
+            if inputs.is_empty() && action.default_gamepad_bindings().is_empty() {
+                continue;
+            }
+            let inputs: Vec<String> = inputs.iter().map(GamepadInput::to_string).collect();
+            let line = format!("{} = {}", action.name(), inputs.join(", "));
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, text)
+    }
+
+    /// Reads the gamepads, and picks up any that were plugged in or unplugged. Should be called
+    /// once per frame, before checking what's held.
+    pub fn update_gamepads(&mut self) {
+        self.gamepads.update(self.axis_threshold);
+    }
+
+    /// Reads what's held on the gamepads partway through a frame, without changing what counts as
+    /// just pressed. The keyboard can't do that, the window only hears about keys once per frame.
+    pub fn poll_gamepads(&mut self) {
+        self.gamepads.poll(self.axis_threshold);
+    }
+
+    /// The Game Boy buttons that are held right now
+    pub fn input_state(&self, window: &Window) -> InputState {
+        InputState {
+            down: self.is_down(window, Action::Down),
+            up: self.is_down(window, Action::Up),
+            left: self.is_down(window, Action::Left),
+            right: self.is_down(window, Action::Right),
+            start: self.is_down(window, Action::Start),
+            select: self.is_down(window, Action::Select),
+            b: self.is_down(window, Action::B),
+            a: self.is_down(window, Action::A),
+        }
+    }
+
+    /// Whether any key or gamepad input bound to the action is held
+    pub fn is_down(&self, window: &Window, action: Action) -> bool {
+        self.bindings_for(action).iter().any(|binding| {
+            window.is_key_down(binding.key) && self.is_binding_active(window, binding)
+        }) || self
+            .gamepad_bindings_for(action)
+            .iter()
+            .any(|&input| self.gamepads.is_held(input))
+    }
+
+    /// Whether any key or gamepad input bound to the action was just pressed. Gamepad inputs
+    /// don't repeat.
+    pub fn is_pressed(&self, window: &Window, action: Action, repeat: KeyRepeat) -> bool {
+        self.bindings_for(action).iter().any(|binding| {
+            window.is_key_pressed(binding.key, repeat) && self.is_binding_active(window, binding)
+        }) || self
+            .gamepad_bindings_for(action)
+            .iter()
+            .any(|&input| self.gamepads.is_pressed(input))
+    }
+
+    fn bindings_for(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .iter()
+            .find(|(other, _)| *other == action)
+            .map_or(&[], |(_, bindings)| bindings)
+    }
+
+    fn gamepad_bindings_for(&self, action: Action) -> &[GamepadInput] {
+        self.gamepad_bindings
+            .iter()
+            .find(|(other, _)| *other == action)
+            .map_or(&[], |(_, inputs)| inputs)
+    }
+
+    // The modifiers have to be held, and a binding on the same key with more of them held wins,
+    // so F1 doesn't go off as well when pressing Shift+F1
+    fn is_binding_active(&self, window: &Window, binding: &Binding) -> bool {
+        binding.modifiers_held(window)
+            && !self
+                .bindings
+                .iter()
+                .flat_map(|(_, bindings)| bindings)
+                .any(|other| {
+                    other.key == binding.key
+                        && other.modifier_count() > binding.modifier_count()
+                        && other.modifiers_held(window)
+                })
+    }
+
+    // Keys that do two things at once are almost always a mistake
+    fn warn_about_conflicts(&self) {
+        warn_about_conflicts(&self.bindings);
+        warn_about_conflicts(&self.gamepad_bindings);
+    }
+}
+
+fn warn_about_conflicts<T: PartialEq + fmt::Display>(bindings: &[(Action, Vec<T>)]) {
+    let all: Vec<(Action, &T)> = bindings
+        .iter()
+        .flat_map(|(action, bindings)| bindings.iter().map(|binding| (*action, binding)))
+        .collect();
+    for (index, (action, binding)) in all.iter().enumerate() {
+        for (other_action, _) in all[index + 1..]
+            .iter()
+            .filter(|(_, other)| other == binding)
+        {
+            println!(
+                "Warning: {binding} is bound to both {} and {}",
+                action.name(),
+                other_action.name()
+            );
+        }
+    }
+}
+
+/// Goes through the Game Boy buttons one by one, binding each to the next key or gamepad input
+/// that's pressed
+pub struct Rebinding {
+    index: usize,
+}
+
+impl Rebinding {
+    pub fn new() -> Rebinding {
+        let rebinding = Rebinding { index: 0 };
+        println!("{}", rebinding.prompt());
+        rebinding
+    }
+
+    pub fn prompt(&self) -> String {
+        format!(
+            "Press a key or gamepad button for {} (Escape keeps the current one)",
+            Action::BUTTONS[self.index].name()
+        )
+    }
+
+    /// Takes the keys and gamepad inputs pressed since the last frame, returns true once every
+    /// button is bound
+    pub fn update(&mut self, window: &Window, controls: &mut Controls) -> bool {
+        // The new key or gamepad input replaces the old ones of its kind, extra ones can be added
+        // in the config file
+        let action = Action::BUTTONS[self.index];
+        if let Some(key) = window.get_keys_pressed(KeyRepeat::No).first().copied() {
+            if key != Key::Escape {
+                controls.bindings[self.index] = (action, vec![Binding::key(key)]);
+            }
+            let keys: Vec<String> = controls.bindings[self.index]
+                .1
+                .iter()
+                .map(Binding::to_string)
+                .collect();
+            println!("{} = {}", action.name(), keys.join(", "));
+        } else if let Some(input) = controls.gamepads.first_pressed() {
+            controls.gamepad_bindings[self.index] = (action, vec![input]);
+            println!("{} = {input} on the gamepad", action.name());
+        } else {
+            return false;
+        }
+
+        self.index += 1;
+        if self.index == Action::BUTTONS.len() {
+            controls.warn_about_conflicts();
+            return true;
+        }
+        println!("{}", self.prompt());
+        false
+    }
+}
+
+/// The per-user config file: in $XDG_CONFIG_HOME or ~/.config on Linux and macOS, and in
+/// %APPDATA% on Windows
+pub fn default_config_path() -> Option<PathBuf> {
+    let directory = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(directory.join("flan_gb_emulator").join("controls.ini"))
+}
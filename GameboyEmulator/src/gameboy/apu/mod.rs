@@ -1 +0,0 @@
-pub mod gb_apu;
@@ -1,4 +0,0 @@
-pub mod gb_cartridge;
-pub mod gb_input;
-pub mod gb_io_registers;
-pub mod gb_misc;
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often /dev/input is checked for gamepads that were just plugged in
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+// Event types from linux/joystick.h. Right after opening a device it sends the state of every
+// button and axis, with JS_EVENT_INIT set as well.
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+const JS_EVENT_INIT: u8 = 0x80;
+
+/// A button on a gamepad, or an axis pushed far enough one way
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GamepadInput {
+    Button(u8),
+    AxisPositive(u8),
+    AxisNegative(u8),
+}
+
+impl GamepadInput {
+    /// Parses the names used in the config file, like Button0, Axis1+ and Axis1-
+    pub fn parse(text: &str) -> Option<GamepadInput> {
+        let text = text.to_ascii_lowercase();
+        if let Some(number) = text.strip_prefix("button") {
+            return number.parse().ok().map(GamepadInput::Button);
+        }
+        let axis = text.strip_prefix("axis")?;
+        if let Some(number) = axis.strip_suffix('+') {
+            return number.parse().ok().map(GamepadInput::AxisPositive);
+        }
+        let number = axis.strip_suffix('-')?;
+        number.parse().ok().map(GamepadInput::AxisNegative)
+    }
+}
+
+impl fmt::Display for GamepadInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GamepadInput::Button(number) => write!(f, "Button{number}"),
+            GamepadInput::AxisPositive(number) => write!(f, "Axis{number}+"),
+            GamepadInput::AxisNegative(number) => write!(f, "Axis{number}-"),
+        }
+    }
+}
+
+// What the reader threads send back
+enum Message {
+    Event {
+        device: usize,
+        kind: u8,
+        number: u8,
+        value: i16,
+    },
+    Disconnected(usize),
+}
+
+struct Gamepad {
+    path: PathBuf,
+    buttons: HashMap<u8, bool>,
+    axes: HashMap<u8, i16>,
+    held: Vec<GamepadInput>,
+    // What was held at the last two calls to update, for telling what was just pressed. Polling
+    // in between only changes what's held.
+    held_at_update: Vec<GamepadInput>,
+    previously_held: Vec<GamepadInput>,
+    just_connected: bool,
+}
+
+impl Gamepad {
+    // Axes count once they're pushed past the threshold
+    fn held_inputs(&self, threshold: i32) -> Vec<GamepadInput> {
+        let mut held: Vec<GamepadInput> = self
+            .buttons
+            .iter()
+            .filter(|(_, &pressed)| pressed)
+            .map(|(&number, _)| GamepadInput::Button(number))
+            .collect();
+        for (&number, &value) in &self.axes {
+            if value as i32 >= threshold {
+                held.push(GamepadInput::AxisPositive(number));
+            } else if value as i32 <= -threshold {
+                held.push(GamepadInput::AxisNegative(number));
+            }
+        }
+        held
+    }
+}
+
+/// Every gamepad plugged in, read through the Linux joystick API. Gamepads can come and go while
+/// the emulator runs, each one is read on its own thread.
+pub struct Gamepads {
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+    devices: HashMap<usize, Gamepad>,
+    next_device: usize,
+    last_scan: Option<Instant>,
+    warned: Vec<PathBuf>,
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Gamepads {
+            sender,
+            receiver,
+            devices: HashMap::new(),
+            next_device: 0,
+            last_scan: None,
+            warned: Vec::new(),
+        }
+    }
+}
+
+impl Gamepads {
+    /// Picks up gamepads that were plugged in or unplugged, and everything that happened on them
+    /// since the last call. Should be called once per frame.
+    pub fn update(&mut self, axis_threshold: f32) {
+        if self
+            .last_scan
+            .is_none_or(|last_scan| last_scan.elapsed() >= SCAN_INTERVAL)
+        {
+            self.scan();
+            self.last_scan = Some(Instant::now());
+        }
+        self.receive();
+
+        // A gamepad that was just plugged in hasn't had anything pressed yet, even if it's held,
+        // like a trigger resting at one end
+        let threshold = threshold(axis_threshold);
+        for gamepad in self.devices.values_mut() {
+            let held = gamepad.held_inputs(threshold);
+            gamepad.previously_held = match gamepad.just_connected {
+                true => held.clone(),
+                false => std::mem::take(&mut gamepad.held_at_update),
+            };
+            gamepad.held_at_update = held.clone();
+            gamepad.held = held;
+            gamepad.just_connected = false;
+        }
+    }
+
+    /// Picks up what's held right now, for the game to see partway through a frame. What was just
+    /// pressed is still only worked out by `update`, so nothing gets missed in between.
+    pub fn poll(&mut self, axis_threshold: f32) {
+        self.receive();
+        let threshold = threshold(axis_threshold);
+        for gamepad in self.devices.values_mut() {
+            gamepad.held = gamepad.held_inputs(threshold);
+        }
+    }
+
+    // Goes through everything the reader threads sent since last time
+    fn receive(&mut self) {
+        while let Ok(message) = self.receiver.try_recv() {
+            match message {
+                Message::Event {
+                    device,
+                    kind,
+                    number,
+                    value,
+                } => {
+                    let Some(gamepad) = self.devices.get_mut(&device) else {
+                        continue;
+                    };
+                    // The state a gamepad starts out in doesn't count as pressing anything
+                    if kind & JS_EVENT_INIT != 0 {
+                        gamepad.just_connected = true;
+                    }
+                    match kind & !JS_EVENT_INIT {
+                        JS_EVENT_BUTTON => {
+                            gamepad.buttons.insert(number, value != 0);
+                        }
+                        JS_EVENT_AXIS => {
+                            gamepad.axes.insert(number, value);
+                        }
+                        _ => (),
+                    }
+                }
+                Message::Disconnected(device) => {
+                    if let Some(gamepad) = self.devices.remove(&device) {
+                        println!("Gamepad disconnected from \"{}\"", gamepad.path.display());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the input is held on any gamepad
+    pub fn is_held(&self, input: GamepadInput) -> bool {
+        self.devices
+            .values()
+            .any(|gamepad| gamepad.held.contains(&input))
+    }
+
+    /// Whether the input was just pressed on any gamepad
+    pub fn is_pressed(&self, input: GamepadInput) -> bool {
+        self.devices.values().any(|gamepad| {
+            gamepad.held_at_update.contains(&input) && !gamepad.previously_held.contains(&input)
+        })
+    }
+
+    /// Something that was just pressed on any gamepad
+    pub fn first_pressed(&self) -> Option<GamepadInput> {
+        self.devices.values().find_map(|gamepad| {
+            gamepad
+                .held_at_update
+                .iter()
+                .find(|input| !gamepad.previously_held.contains(input))
+                .copied()
+        })
+    }
+
+    // Opens every joystick device that isn't open yet
+    fn scan(&mut self) {
+        let Ok(entries) = fs::read_dir("/dev/input") else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_joystick = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("js"));
+            if !is_joystick || self.devices.values().any(|gamepad| gamepad.path == path) {
+                continue;
+            }
+
+            // Permissions are often set up just after the device shows up, so keep trying, but
+            // only complain once
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    if !self.warned.contains(&path) {
+                        println!("Unable to open gamepad \"{}\": {e}", path.display());
+                        self.warned.push(path);
+                    }
+                    continue;
+                }
+            };
+            println!("Gamepad connected on \"{}\"", path.display());
+
+            let device = self.next_device;
+            self.next_device += 1;
+            self.devices.insert(
+                device,
+                Gamepad {
+                    path,
+                    buttons: HashMap::new(),
+                    axes: HashMap::new(),
+                    held: Vec::new(),
+                    held_at_update: Vec::new(),
+                    previously_held: Vec::new(),
+                    just_connected: true,
+                },
+            );
+            let sender = self.sender.clone();
+            thread::spawn(move || read_events(device, file, sender));
+        }
+    }
+}
+
+fn threshold(axis_threshold: f32) -> i32 {
+    (axis_threshold * i16::MAX as f32) as i32
+}
+
+// Reads js_event structs until the gamepad is unplugged, or until nobody's listening anymore
+fn read_events(device: usize, mut file: File, sender: Sender<Message>) {
+    let mut event = [0u8; 8];
+    while file.read_exact(&mut event).is_ok() {
+        // The first 4 bytes are a timestamp we don't need
+        let message = Message::Event {
+            device,
+            kind: event[6],
+            number: event[7],
+            value: i16::from_ne_bytes([event[4], event[5]]),
+        };
+        if sender.send(message).is_err() {
+            return;
+        }
+    }
+    let _ = sender.send(Message::Disconnected(device));
+}
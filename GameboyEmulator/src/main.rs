@@ -1,19 +1,47 @@
 use std::{
     env,
+    fs::{self, File},
+    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
 
-use minifb::{Key, Window, WindowOptions};
-
-use crate::gameboy::{GameBoy, InputState};
+use audio_queue::AudioQueue;
+use controls::{Action, Controls, Rebinding};
+use flan_gb_core::{AudioMode, GameBoy, InputState, LinkCable, MemoryInit, Printer, RewindBuffer};
+use minifb::{KeyRepeat, Window, WindowOptions};
+use movies::{MoviePlayback, MovieRecording};
+use rodio::{
+    cpal::{self, traits::HostTrait},
+    DeviceTrait, OutputStream, Sink,
+};
+use time_stretch::TimeStretch;
 
-mod gameboy;
+mod audio_queue;
+mod controls;
+mod gamepad;
+mod movies;
+mod time_stretch;
 
+const WINDOW_TITLE: &str = "Flan's Game Boy Emulator";
 const DEBUG_WIDTH: usize = 1280;
 const DEBUG_HEIGHT: usize = 720;
 const DEBUG_VIEW_ENABLE: bool = true;
 const WIDTH: usize = 642;
 const HEIGHT: usize = 578;
+const REWIND_CAPACITY: usize = 600; // 10 seconds of frames
+const REWIND_INTERVAL: u32 = 1;
+// How long a rewound or paused frame is shown for
+const FRAME_DURATION: Duration = Duration::from_nanos(70224 * 1_000_000_000 / 4_194_304);
+// Frames run in slices this long, and the gamepads are read in between (16 scanlines)
+const INPUT_POLL_DOTS: u64 = 16 * 456;
+// Falling further behind than this doesn't make us rush to catch up
+const MAX_FRAME_LAG: Duration = Duration::from_millis(100);
+const DEFAULT_AUDIO_LATENCY_MS: u64 = 50;
+// How much the audio rate may be bent to keep the audio queue at the right length
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+// The speeds - and = step through
+const SPEED_STEPS: [f64; 7] = [0.125, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const DEFAULT_FAST_FORWARD_SPEED: f64 = 4.0;
 
 fn main() {
     // Create window
@@ -26,7 +54,7 @@ fn main() {
     let mut buffer: Vec<u32> = vec![0; (w * h) as _];
 
     let mut window = Window::new(
-        "Flan's Game Boy Emulator",
+        WINDOW_TITLE,
         w,
         h,
         WindowOptions {
@@ -36,52 +64,443 @@ fn main() {
     )
     .expect("Unable to open Window");
 
+    // We keep our own time, so don't let the window sleep on its own as well
+    window.set_target_fps(0);
+
+    // Open the audio device, if there is one
+    let audio = OutputStream::try_default()
+        .ok()
+        .and_then(|(stream, handle)| Some((stream, Sink::try_new(&handle).ok()?)));
+    if audio.is_none() {
+        println!("Unable to open audio device, running without sound");
+    }
+
     // Get our Game Boy
     let mut game_boy = GameBoy::new();
 
-    // Insert a cartridge
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        game_boy.insert_cartridge(args[1].as_str());
+    // Parse the command line, anything that isn't a flag is the ROM path
+    let mut rom_path = String::new();
+    let mut debug = false;
+    let mut trace_path = None;
+    let mut link_listen = None;
+    let mut link_connect = None;
+    let mut printer = false;
+    let mut screenshot_scale = 1;
+    let mut record_channels = false;
+    let mut fast_audio = false;
+    let mut sync_mode = SyncMode::Video;
+    let mut audio_latency = Duration::from_millis(DEFAULT_AUDIO_LATENCY_MS);
+    let mut speed = 1.0;
+    let mut fast_forward_speed = DEFAULT_FAST_FORWARD_SPEED;
+    let mut stretch_off_speed_audio = true;
+    let mut controls_path = controls::default_config_path();
+    let mut record_movie = None;
+    let mut play_movie = None;
+    let mut memory_init = None;
+    let mut block_opposite_directions = false;
+    let mut movie_author = String::new();
+    let mut movie_description = String::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--debug" => debug = true,
+            "--trace" => trace_path = args.next(),
+            "--link-listen" => link_listen = args.next(),
+            "--link-connect" => link_connect = args.next(),
+            "--printer" => printer = true,
+            "--controls" => controls_path = args.next().map(PathBuf::from),
+            "--record-movie" => record_movie = args.next().map(PathBuf::from),
+            "--play-movie" => play_movie = args.next(),
+            "--memory-init" => match args.next().unwrap_or_default().parse::<MemoryInit>() {
+                Ok(mode) => memory_init = Some(mode),
+                Err(e) => println!("{e}"),
+            },
+            "--movie-author" => movie_author = args.next().unwrap_or_default(),
+            "--movie-description" => movie_description = args.next().unwrap_or_default(),
+            "--screenshot-scale" => {
+                screenshot_scale = args
+                    .next()
+                    .and_then(|scale| scale.parse().ok())
+                    .unwrap_or(1)
+            }
+            "--record-channels" => record_channels = true,
+            "--fast-audio" => fast_audio = true,
+            "--block-opposite-directions" => block_opposite_directions = true,
+            "--sync" => match args.next().as_deref() {
+                Some("video") => sync_mode = SyncMode::Video,
+                Some("audio") => sync_mode = SyncMode::Audio,
+                other => println!("Unknown sync mode {other:?}, use \"video\" or \"audio\""),
+            },
+            "--speed" => {
+                let parsed = args.next().and_then(|speed| speed.parse::<f64>().ok());
+                speed = parsed.filter(|&speed| speed > 0.0).unwrap_or(1.0);
+            }
+            "--fast-forward" => {
+                let parsed = args.next().and_then(|speed| speed.parse::<f64>().ok());
+                fast_forward_speed = parsed
+                    .filter(|&speed| speed >= 0.0)
+                    .unwrap_or(DEFAULT_FAST_FORWARD_SPEED);
+            }
+            "--off-speed-audio" => match args.next().as_deref() {
+                Some("stretch") => stretch_off_speed_audio = true,
+                Some("mute") => stretch_off_speed_audio = false,
+                other => println!("Unknown off-speed audio {other:?}, use \"stretch\" or \"mute\""),
+            },
+            "--audio-latency" => {
+                let milliseconds = args.next().and_then(|ms| ms.parse().ok());
+                audio_latency =
+                    Duration::from_millis(milliseconds.unwrap_or(DEFAULT_AUDIO_LATENCY_MS).max(1));
+            }
+            _ => rom_path = arg,
+        }
+    }
+
+    // Fill memory the way we're asked to before the cartridge goes in, so its RAM gets the same
+    if let Some(memory_init) = memory_init {
+        game_boy.set_memory_init(memory_init);
+    }
+
+    // Insert a cartridge. Movies power on the way they were recorded and without the save file,
+    // so they play back the same way every time.
+    let mut movie_playback = None;
+    if let Some(path) = &play_movie {
+        match MoviePlayback::start(path, &rom_path) {
+            Ok((playback, movie_game_boy)) => {
+                movie_playback = Some(playback);
+                game_boy = movie_game_boy;
+            }
+            Err(e) => println!("Unable to play movie \"{path}\": {e}"),
+        }
+    }
+    if movie_playback.is_some() {
+        if record_movie.take().is_some() {
+            println!("Can't record a movie while playing one back, not recording");
+        }
+        if memory_init.is_some() {
+            println!(
+                "Movies power on with the memory they were recorded with, ignoring --memory-init"
+            );
+        }
+    } else if record_movie.is_some() {
+        match fs::read(&rom_path) {
+            Ok(rom) => {
+                if !game_boy.load_rom(rom) {
+                    println!("Unable to load ROM file \"{rom_path}\", it's too small to be a Game Boy ROM");
+                }
+            }
+            Err(e) => println!("Unable to load ROM file \"{rom_path}\", error message: {e}"),
+        }
     } else {
-        game_boy.insert_cartridge("");
+        game_boy.insert_cartridge(rom_path.as_str());
+    }
+
+    // Produce audio at the device's own sample rate, so it doesn't have to be resampled again
+    if fast_audio {
+        game_boy.set_audio_mode(AudioMode::Fast);
+    } else if let Some(sample_rate) = device_sample_rate() {
+        game_boy.set_audio_sample_rate(sample_rate);
     }
 
+    // Everything the Game Boy plays goes through one queue, so we always know how much is waiting
+    let audio_queue = AudioQueue::new(game_boy.audio_sample_rate());
+    match &audio {
+        Some((_, sink)) => sink.append(audio_queue.source()),
+        None if sync_mode == SyncMode::Audio => {
+            println!("No audio device to sync to, syncing to video instead");
+            sync_mode = SyncMode::Video;
+        }
+        None => (),
+    }
+
+    // Trace logs are compared against reference logs that start at the cartridge entry point
+    if let Some(path) = trace_path {
+        match File::create(&path) {
+            Ok(file) => {
+                game_boy.skip_boot_rom();
+                game_boy.start_trace_log(Box::new(file));
+            }
+            Err(e) => println!("Unable to create trace log \"{path}\": {e}"),
+        }
+    }
+
+    // Plug in a link cable to another emulator
+    let link_cable = match (link_listen, link_connect) {
+        (Some(address), _) => {
+            println!("Waiting for another emulator to connect to {address}");
+            Some(LinkCable::listen(address.as_str()))
+        }
+        (None, Some(address)) => Some(LinkCable::connect(address.as_str())),
+        (None, None) => None,
+    };
+    match link_cable {
+        Some(Ok(link_cable)) => {
+            println!("Link cable connected");
+            game_boy.connect_serial(Box::new(link_cable));
+        }
+        Some(Err(e)) => println!("Unable to connect link cable: {e}"),
+        None => (),
+    }
+
+    // Or a printer, which saves its pages next to the ROM
+    if printer {
+        game_boy.connect_serial(Box::new(Printer::new(&rom_path)));
+    }
+
+    // Start paused in the debugger if asked to
+    if debug {
+        game_boy.debugger_break();
+    }
+
+    // Start recording a movie from here, with the boot ROM skipped or not
+    let mut movie_recording = record_movie
+        .map(|path| MovieRecording::new(&game_boy, path, movie_author, movie_description));
+
+    // Load the key bindings
+    let mut controls = match &controls_path {
+        Some(path) => Controls::load_or_create(path),
+        None => Controls::default(),
+    };
+
     // Keep track of timing and input
-    let mut now = Instant::now();
-    let mut input_state = InputState::default();
+    let mut next_frame = Instant::now();
+    let mut last_emulated_time = game_boy.emulated_time();
+    let mut rewind_buffer = RewindBuffer::new(REWIND_CAPACITY, REWIND_INTERVAL);
+    let mut time_stretch = TimeStretch::new();
+    let mut paused = false;
+    let mut audio_playing = false;
+    let mut rebinding: Option<Rebinding> = None;
 
     while window.is_open() {
-        // Handle delta time
-        let dt = now.elapsed().as_secs_f32();
-        let desired_dt = 69905_f32 / (1 << 22) as f32;
-        if dt < desired_dt {
-            std::thread::sleep(Duration::from_secs_f32(desired_dt - dt));
-        }
-
-        now = Instant::now();
-
-        // Get input
-        // todo: remappable controls
-        input_state.down = window.is_key_down(Key::Down);
-        input_state.up = window.is_key_down(Key::Up);
-        input_state.left = window.is_key_down(Key::Left);
-        input_state.right = window.is_key_down(Key::Right);
-        input_state.start = window.is_key_down(Key::Enter);
-        input_state.select = window.is_key_down(Key::RightShift);
-        input_state.b = window.is_key_down(Key::Z);
-        input_state.a = window.is_key_down(Key::X);
-        dbg!(&input_state);
+        // Wait until it's time for the next frame
+        if sync_mode == SyncMode::Audio && audio_playing {
+            // The audio device sets the pace, run another frame once the queue runs low
+            let waiting_since = Instant::now();
+            while audio_queue.buffered() > audio_latency && waiting_since.elapsed() < MAX_FRAME_LAG
+            {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        } else {
+            let now = Instant::now();
+            match next_frame.checked_duration_since(now) {
+                Some(wait) => std::thread::sleep(wait),
+                // Don't rush to catch up after a stall, like sitting in the debugger
+                None if now - next_frame > MAX_FRAME_LAG => next_frame = now,
+                None => (),
+            }
+        }
+
+        // Catch up on the gamepads, and pick up any that were plugged in
+        controls.update_gamepads();
+
+        // While rebinding, keys go to the rebinding instead of the game, which waits
+        if let Some(rebind) = &mut rebinding {
+            match rebind.update(&window, &mut controls) {
+                true => {
+                    rebinding = None;
+                    window.set_title(WINDOW_TITLE);
+                    save_controls(&controls, controls_path.as_deref());
+                }
+                false => window.set_title(&rebind.prompt()),
+            }
+            audio_playing = false;
+            next_frame += FRAME_DURATION;
+            window.update_with_buffer(&buffer, w, h).unwrap();
+            continue;
+        }
+
+        // Get input, from the movie while one is playing
+        let input_state = match movie_playback.as_ref().and_then(MoviePlayback::input) {
+            Some(input_state) => input_state,
+            None => held_buttons(&controls, &window, block_opposite_directions),
+        };
         game_boy.update_input(&input_state);
 
-        // Simulate one frame on Game Boy
-        game_boy.run_frame();
+        // Load and save states, one slot per key. A movie being recorded carries on from the
+        // loaded state if it was saved during the recording, and ends otherwise.
+        for slot in 1..=controls::SAVE_STATE_SLOTS {
+            if controls.is_pressed(&window, Action::LoadState(slot), KeyRepeat::No)
+                && handle_save_state_slot(&mut game_boy, &rom_path, slot, false)
+            {
+                // Rewinding from here shouldn't go back to before the state was loaded
+                rewind_buffer.clear();
+                stop_movie_playback(&mut movie_playback);
+                if movie_recording
+                    .as_mut()
+                    .is_some_and(|recording| !recording.loaded_state(slot))
+                {
+                    println!(
+                        "That state wasn't saved during the movie, so the recording ends here"
+                    );
+                    stop_movie_recording(&mut movie_recording);
+                }
+            }
+            if controls.is_pressed(&window, Action::SaveState(slot), KeyRepeat::No)
+                && handle_save_state_slot(&mut game_boy, &rom_path, slot, true)
+            {
+                if let Some(recording) = &mut movie_recording {
+                    recording.saved_state(slot);
+                }
+            }
+        }
+
+        // Screenshots are saved next to the ROM
+        if controls.is_pressed(&window, Action::Screenshot, KeyRepeat::No) {
+            save_screenshot(&game_boy, &rom_path, screenshot_scale);
+        }
+
+        // Start and stop recording the audio
+        if controls.is_pressed(&window, Action::RecordAudio, KeyRepeat::No) {
+            toggle_audio_recording(&mut game_boy, &rom_path, record_channels);
+        }
+
+        // Break into the debugger on the console
+        if controls.is_pressed(&window, Action::Debugger, KeyRepeat::No) {
+            game_boy.debugger_break();
+        }
+
+        // Turn it off and on again, saving the game first like the cartridge would have
+        // Movies can't hold a reset, so they end here
+        if controls.is_pressed(&window, Action::Reset, KeyRepeat::No) {
+            game_boy.save_game_if_possible();
+            game_boy.reset();
+            println!("Reset");
+            stop_movie_playback(&mut movie_playback);
+            stop_movie_recording(&mut movie_recording);
+        }
+
+        // Start rebinding the buttons from the next frame on
+        if controls.is_pressed(&window, Action::Rebind, KeyRepeat::No) {
+            rebinding = Some(Rebinding::new());
+        }
+
+        // Pause, and while paused run a single frame at a time
+        if controls.is_pressed(&window, Action::Pause, KeyRepeat::No) {
+            paused = !paused;
+            println!("{}", if paused { "Paused" } else { "Unpaused" });
+        }
+        let frame_advance =
+            paused && controls.is_pressed(&window, Action::FrameAdvance, KeyRepeat::Yes);
+
+        // Slow down and speed up in steps, or hold to fast-forward (None is as fast as we can)
+        if controls.is_pressed(&window, Action::SlowDown, KeyRepeat::No) {
+            speed = *SPEED_STEPS
+                .iter()
+                .rev()
+                .find(|&&step| step < speed)
+                .unwrap_or(&SPEED_STEPS[0]);
+            println!("Speed: {speed}x");
+        }
+        if controls.is_pressed(&window, Action::SpeedUp, KeyRepeat::No) {
+            speed = *SPEED_STEPS
+                .iter()
+                .find(|&&step| step > speed)
+                .unwrap_or(&SPEED_STEPS[6]);
+            println!("Speed: {speed}x");
+        }
+        let current_speed = match controls.is_down(&window, Action::FastForward) {
+            true if fast_forward_speed == 0.0 => None,
+            true => Some(fast_forward_speed),
+            false => Some(speed),
+        };
+
+        // Holding rewind plays the game backwards, one snapshot per frame
+        let rewinding = controls.is_down(&window, Action::Rewind);
+        let running = !rewinding && (!paused || frame_advance);
+        if rewinding {
+            if rewind_buffer.rewind(&mut game_boy) {
+                stop_movie_playback(&mut movie_playback);
+                if let Some(recording) = &mut movie_recording {
+                    recording.rewound_frame();
+                }
+            }
+        } else if running {
+            // Simulate a frame on the Game Boy in slices, so the game sees gamepad input change
+            // partway through like on hardware. At a set speed the slices are spread over the
+            // frame's time, or there'd be nothing new to see. Movies hold one input per frame, so
+            // those frames run in one go.
+            let poll_input = movie_recording.is_none() && movie_playback.is_none();
+            let slice_pacing = match sync_mode {
+                SyncMode::Video => current_speed,
+                SyncMode::Audio => None,
+            };
+            // Paced from when the frame was due, so a late frame still catches up
+            let frame_started = (next_frame, game_boy.emulated_time());
+            while !game_boy.run_frame_for(INPUT_POLL_DOTS) {
+                if !poll_input {
+                    continue;
+                }
+                if let Some(speed) = slice_pacing {
+                    let due = frame_started.0
+                        + (game_boy.emulated_time() - frame_started.1).div_f64(speed);
+                    if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                        std::thread::sleep(wait);
+                    }
+                }
+                controls.poll_gamepads();
+                game_boy.update_input(&held_buttons(&controls, &window, block_opposite_directions));
+            }
+
+            // Stop in the debugger whenever it asks to
+            while game_boy.debugger_paused().is_some() {
+                game_boy.debugger_repl();
+                game_boy.run_frame();
+            }
+            rewind_buffer.record_frame(&game_boy);
+
+            if let Some(recording) = &mut movie_recording {
+                recording.record_frame(&input_state, &game_boy);
+            }
+            if let Some(playback) = &mut movie_playback {
+                if playback.frame_finished(&game_boy) {
+                    movie_playback = None;
+                }
+            }
+        }
+
+        // The next frame is due once our clock has caught up with the Game Boy's, at the chosen speed
+        let emulated_time = game_boy.emulated_time();
+        let frame_time = emulated_time - last_emulated_time;
+        last_emulated_time = emulated_time;
+        match (running, current_speed) {
+            (false, _) => next_frame += FRAME_DURATION,
+            (true, Some(speed)) => next_frame += frame_time.div_f64(speed),
+            (true, None) => next_frame = Instant::now(),
+        }
+
+        // Off speed, the audio is stretched back to real time so it keeps its pitch, or muted.
+        // Single frames and going flat out are always muted.
+        let samples = game_boy.take_audio_samples();
+        let samples = match current_speed {
+            _ if !running || frame_advance => None,
+            Some(1.0) => Some(samples),
+            Some(speed) if stretch_off_speed_audio => Some(time_stretch.process(&samples, speed)),
+            _ => None,
+        };
+        if current_speed == Some(1.0) || samples.is_none() {
+            time_stretch.reset();
+        }
+        audio_playing = samples.is_some();
+
+        // Queue up the audio, but drop it rather than let the delay keep growing
+        if let (Some(samples), Some(_)) = (samples, &audio) {
+            if audio_queue.buffered() < audio_latency * 2 {
+                audio_queue.push(&samples);
+            }
+
+            // When the frames keep the time, bend the audio rate a little to keep the queue at the
+            // right length, so the audio device's clock and ours never drift apart
+            if sync_mode == SyncMode::Video {
+                let fill = audio_queue.buffered().as_secs_f64() / audio_latency.as_secs_f64();
+                let adjustment = MAX_RATE_ADJUSTMENT * (1.0 - fill).clamp(-1.0, 1.0);
+                game_boy.set_audio_rate_adjustment(1.0 + adjustment);
+            }
+        }
 
         // Render parts of memory
         if DEBUG_VIEW_ENABLE {
-            game_boy.render_memory(&mut buffer, 0x8000, 16, 24, 8, 8, 2);
-            game_boy.render_memory(&mut buffer, 0x0000, 32, 32, 272, 8, 1);
-            game_boy.render_memory(&mut buffer, 0x4000, 32, 32, 536, 8, 1);
+            game_boy.render_memory(&mut buffer, 0x8000, 16, 24, 8, 8, 2, DEBUG_WIDTH);
+            game_boy.render_memory(&mut buffer, 0x0000, 32, 32, 272, 8, 1, DEBUG_WIDTH);
+            game_boy.render_memory(&mut buffer, 0x4000, 32, 32, 536, 8, 1, DEBUG_WIDTH);
             game_boy.render_palettes(&mut buffer, 272, 272, 24, DEBUG_WIDTH);
             game_boy.render_screen(&mut buffer, 792, 8, 2, DEBUG_WIDTH);
         } else {
@@ -89,4 +508,130 @@ fn main() {
         }
         window.update_with_buffer(&buffer, w, h).unwrap();
     }
+
+    if let Err(e) = game_boy.stop_audio_recording() {
+        println!("Unable to finish audio recording: {e}");
+    }
+    stop_movie_recording(&mut movie_recording);
+    game_boy.save_game_if_possible();
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SyncMode {
+    // Run frames at the Game Boy's own refresh rate, and bend the audio to fit
+    Video,
+    // Run frames whenever the audio device needs more samples
+    Audio,
+}
+
+fn device_sample_rate() -> Option<u32> {
+    let device = cpal::default_host().default_output_device()?;
+    Some(device.default_output_config().ok()?.sample_rate().0)
+}
+
+fn save_controls(controls: &Controls, path: Option<&Path>) {
+    let Some(path) = path else {
+        println!("Nowhere to save the controls to, start with --controls FILE to keep them");
+        return;
+    };
+    match controls.save(path) {
+        Ok(()) => println!("Saved controls to \"{}\"", path.display()),
+        Err(e) => println!("Unable to write controls \"{}\": {e}", path.display()),
+    }
+}
+
+fn held_buttons(
+    controls: &Controls,
+    window: &Window,
+    block_opposite_directions: bool,
+) -> InputState {
+    match block_opposite_directions {
+        true => controls.input_state(window).without_opposite_directions(),
+        false => controls.input_state(window),
+    }
+}
+
+fn stop_movie_playback(movie_playback: &mut Option<MoviePlayback>) {
+    if movie_playback.take().is_some() {
+        println!("Stopped playing the movie");
+    }
+}
+
+fn stop_movie_recording(movie_recording: &mut Option<MovieRecording>) {
+    if let Some(recording) = movie_recording.take() {
+        recording.finish();
+    }
+}
+
+// Returns true if the state was saved or loaded
+fn handle_save_state_slot(game_boy: &mut GameBoy, rom_path: &str, slot: usize, save: bool) -> bool {
+    // Save states live next to the ROM, as rom_name.ss1 through rom_name.ss9
+    let path = Path::new(rom_path).with_extension(format!("ss{slot}"));
+
+    if save {
+        return match std::fs::write(&path, game_boy.save_state()) {
+            Ok(()) => {
+                println!("Saved state to slot {slot}");
+                true
+            }
+            Err(e) => {
+                println!("Unable to write save state \"{}\": {e}", path.display());
+                false
+            }
+        };
+    }
+
+    match std::fs::read(&path) {
+        Ok(bytes) => match game_boy.load_state(&bytes) {
+            Ok(()) => {
+                println!("Loaded state from slot {slot}");
+                true
+            }
+            Err(e) => {
+                println!("Unable to load save state from slot {slot}: {e}");
+                false
+            }
+        },
+        Err(e) => {
+            println!("Unable to read save state \"{}\": {e}", path.display());
+            false
+        }
+    }
+}
+
+fn save_screenshot(game_boy: &GameBoy, rom_path: &str, scale: usize) {
+    // Screenshots live next to the ROM as rom_name.screenshot1.png and up, without overwriting older ones
+    let path = (1..)
+        .map(|number| Path::new(rom_path).with_extension(format!("screenshot{number}.png")))
+        .find(|path| !path.exists())
+        .unwrap();
+
+    match game_boy.save_screenshot(&path, scale) {
+        Ok(()) => println!("Saved screenshot to \"{}\"", path.display()),
+        Err(e) => println!("Unable to save screenshot \"{}\": {e}", path.display()),
+    }
+}
+
+fn toggle_audio_recording(game_boy: &mut GameBoy, rom_path: &str, separate_channels: bool) {
+    if game_boy.is_recording_audio() {
+        match game_boy.stop_audio_recording() {
+            Ok(()) => println!("Stopped recording audio"),
+            Err(e) => println!("Unable to finish audio recording: {e}"),
+        }
+        return;
+    }
+
+    // Recordings live next to the ROM as rom_name.recording1.wav and up, like screenshots
+    let path = (1..)
+        .map(|number| Path::new(rom_path).with_extension(format!("recording{number}.wav")))
+        .find(|path| !path.exists())
+        .unwrap();
+
+    match game_boy.start_audio_recording(&path, separate_channels) {
+        Ok(()) => println!("Recording audio to \"{}\"", path.display()),
+        Err(e) => println!(
+            "Unable to create audio recording \"{}\": {e}",
+            path.display()
+        ),
+    }
 }
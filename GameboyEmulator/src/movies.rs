@@ -0,0 +1,140 @@
+use std::{fs, path::PathBuf};
+
+use flan_gb_core::{GameBoy, InputState, Movie};
+
+use crate::controls::SAVE_STATE_SLOTS;
+
+/// A movie being recorded. Loading a save state or rewinding records over the rest of it, as
+/// long as we know which frame the game went back to.
+pub struct MovieRecording {
+    movie: Movie,
+    path: PathBuf,
+    slot_frames: [Option<usize>; SAVE_STATE_SLOTS],
+    rewinding: bool,
+}
+
+impl MovieRecording {
+    pub fn new(game_boy: &GameBoy, path: PathBuf, author: String, description: String) -> Self {
+        println!("Recording a movie to \"{}\"", path.display());
+        let mut movie = Movie::new(game_boy);
+        movie.author = author;
+        movie.description = description;
+        MovieRecording {
+            movie,
+            path,
+            slot_frames: [None; SAVE_STATE_SLOTS],
+            rewinding: false,
+        }
+    }
+
+    pub fn record_frame(&mut self, input: &InputState, game_boy: &GameBoy) {
+        self.movie.record_frame(input, game_boy);
+        self.rewinding = false;
+    }
+
+    pub fn rewound_frame(&mut self) {
+        // Holding rewind for a while only counts as going back once
+        if !self.rewinding {
+            self.movie.add_rerecord();
+            self.rewinding = true;
+        }
+        self.truncate(self.movie.len().saturating_sub(1));
+    }
+
+    pub fn saved_state(&mut self, slot: usize) {
+        self.slot_frames[slot - 1] = Some(self.movie.len());
+    }
+
+    /// Returns false if the state wasn't saved during this recording, so the movie can't go on
+    pub fn loaded_state(&mut self, slot: usize) -> bool {
+        let Some(frame) = self.slot_frames[slot - 1] else {
+            return false;
+        };
+        self.truncate(frame);
+        self.movie.add_rerecord();
+        true
+    }
+
+    // States saved after this point belong to frames that are about to be recorded over, so
+    // loading one later would carry on from input that's no longer in the movie
+    fn truncate(&mut self, length: usize) {
+        self.movie.truncate(length);
+        for slot_frame in &mut self.slot_frames {
+            if slot_frame.is_some_and(|frame| frame > length) {
+                *slot_frame = None;
+            }
+        }
+    }
+
+    pub fn finish(self) {
+        match fs::write(&self.path, self.movie.to_bytes()) {
+            Ok(()) => println!(
+                "Saved movie to \"{}\", {} frames and {} rerecords",
+                self.path.display(),
+                self.movie.len(),
+                self.movie.rerecords()
+            ),
+            Err(e) => println!("Unable to write movie \"{}\": {e}", self.path.display()),
+        }
+    }
+}
+
+/// A movie being played back, which checks every frame against the recording
+pub struct MoviePlayback {
+    movie: Movie,
+    frame: usize,
+    desynced: bool,
+}
+
+impl MoviePlayback {
+    /// Loads a movie, and powers on a Game Boy the way it was recorded
+    pub fn start(movie_path: &str, rom_path: &str) -> Result<(MoviePlayback, GameBoy), String> {
+        let bytes = fs::read(movie_path).map_err(|e| e.to_string())?;
+        let movie = Movie::from_bytes(&bytes).map_err(|e| e.to_string())?;
+        let rom = fs::read(rom_path).map_err(|e| e.to_string())?;
+        let game_boy = movie.power_on(rom).map_err(|e| e.to_string())?;
+
+        println!(
+            "Playing movie by \"{}\", {} frames and {} rerecords: {}",
+            movie.author,
+            movie.len(),
+            movie.rerecords(),
+            movie.description
+        );
+        let playback = MoviePlayback {
+            movie,
+            frame: 0,
+            desynced: false,
+        };
+        Ok((playback, game_boy))
+    }
+
+    pub fn input(&self) -> Option<InputState> {
+        self.movie.input(self.frame)
+    }
+
+    /// Checks the frame that just ran, returns true once the movie is over
+    pub fn frame_finished(&mut self, game_boy: &GameBoy) -> bool {
+        if self.frame >= self.movie.len() {
+            return true;
+        }
+
+        // Only the first desync is worth telling about, everything after it follows from it
+        if !self.desynced {
+            if let Err(e) = self.movie.verify_frame(self.frame, game_boy) {
+                println!("Warning: {e}");
+                self.desynced = true;
+            }
+        }
+
+        self.frame += 1;
+        if self.frame < self.movie.len() {
+            return false;
+        }
+        match self.desynced {
+            true => println!("Movie finished, but it desynced along the way"),
+            false => println!("Movie finished without desyncing"),
+        }
+        true
+    }
+}
@@ -0,0 +1,116 @@
+use std::f32::consts::PI;
+
+// Audio is cut into grains of this many frames, which overlap by half
+const GRAIN_FRAMES: usize = 1024;
+const HOP_FRAMES: usize = GRAIN_FRAMES / 2;
+// How far a grain may be moved to line up with the one before it
+const SEARCH_FRAMES: usize = 256;
+const SEARCH_STEP: usize = 4;
+
+/// Makes interleaved stereo audio play faster or slower without changing its pitch, by cutting
+/// it into short grains and crossfading them back together closer together or further apart.
+/// Every grain is nudged to where it lines up best with the one before, so tones don't warble.
+pub struct TimeStretch {
+    window: Vec<f32>,
+    input: Vec<i16>,
+    position: f64,
+    previous_start: Option<usize>,
+    overlap: Vec<f32>,
+}
+
+impl TimeStretch {
+    pub fn new() -> TimeStretch {
+        TimeStretch {
+            // Half overlapping Hann windows add up to exactly 1
+            window: (0..GRAIN_FRAMES)
+                .map(|frame| 0.5 - 0.5 * (2.0 * PI * frame as f32 / GRAIN_FRAMES as f32).cos())
+                .collect(),
+            input: Vec::new(),
+            position: 0.0,
+            previous_start: None,
+            overlap: vec![0.0; HOP_FRAMES * 2],
+        }
+    }
+
+    /// Forgets everything that's waiting, for when the audio doesn't carry on from before
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+        self.previous_start = None;
+        self.overlap.fill(0.0);
+    }
+
+    /// Takes audio recorded at `speed` times real time, and gives back audio that plays at real time
+    pub fn process(&mut self, samples: &[i16], speed: f64) -> Vec<i16> {
+        self.input.extend_from_slice(samples);
+
+        let mut output = Vec::new();
+        while self.position as usize + SEARCH_FRAMES + GRAIN_FRAMES <= self.input.len() / 2 {
+            let start = self.best_start(self.position as usize);
+            let grain = &self.input[start * 2..(start + GRAIN_FRAMES) * 2];
+
+            // The first half finishes off the previous grain, the second half waits for the next one
+            for (index, (&sample, overlap)) in grain.iter().zip(&mut self.overlap).enumerate() {
+                let value = *overlap + sample as f32 * self.window[index / 2];
+                output.push(value.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+            }
+            for (index, overlap) in self.overlap.iter_mut().enumerate() {
+                *overlap =
+                    grain[HOP_FRAMES * 2 + index] as f32 * self.window[HOP_FRAMES + index / 2];
+            }
+
+            // Going faster skips ahead further in the input for the same amount of output
+            self.previous_start = Some(start);
+            self.position += HOP_FRAMES as f64 * speed;
+        }
+
+        // Let go of the input we're done with, but keep what the next search may look at
+        let needed = (self.position as usize).saturating_sub(SEARCH_FRAMES);
+        let consumed = needed
+            .min(self.previous_start.unwrap_or(usize::MAX))
+            .min(self.input.len() / 2);
+        self.input.drain(..consumed * 2);
+        self.position -= consumed as f64;
+        self.previous_start = self.previous_start.map(|start| start - consumed);
+        output
+    }
+
+    // Finds the start near `nominal` that carries on from the previous grain most smoothly
+    fn best_start(&self, nominal: usize) -> usize {
+        let Some(previous) = self.previous_start else {
+            return nominal;
+        };
+
+        // Where the previous grain would have carried on to, if we hadn't skipped or repeated
+        let target = previous + HOP_FRAMES;
+        let first = nominal.saturating_sub(SEARCH_FRAMES);
+        let last = nominal + SEARCH_FRAMES;
+
+        // Search roughly first, then close in around the best match
+        let coarse = self.best_match(target, (first..=last).step_by(SEARCH_STEP));
+        let fine_first = coarse.saturating_sub(SEARCH_STEP).max(first);
+        let fine_last = (coarse + SEARCH_STEP).min(last);
+        self.best_match(target, fine_first..=fine_last)
+    }
+
+    fn best_match(&self, target: usize, candidates: impl Iterator<Item = usize>) -> usize {
+        let mono = |frame: usize| self.input[frame * 2] as f32 + self.input[frame * 2 + 1] as f32;
+
+        let mut best = (f32::MIN, target);
+        for candidate in candidates {
+            let mut correlation = 0.0;
+            let mut energy = 1.0;
+            for offset in 0..HOP_FRAMES {
+                let sample = mono(candidate + offset);
+                correlation += sample * mono(target + offset);
+                energy += sample * sample;
+            }
+
+            let score = correlation / energy.sqrt();
+            if score > best.0 {
+                best = (score, candidate);
+            }
+        }
+        best.1
+    }
+}